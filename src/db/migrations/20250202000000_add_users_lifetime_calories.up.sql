@@ -0,0 +1,10 @@
+ALTER TABLE users ADD COLUMN IF NOT EXISTS lifetime_calories BIGINT NOT NULL DEFAULT 0;
+
+UPDATE users u
+SET lifetime_calories = totals.calories
+FROM (
+    SELECT user_id, SUM(calories_burned) AS calories
+    FROM activities
+    GROUP BY user_id
+) totals
+WHERE u.user_id = totals.user_id;
@@ -0,0 +1 @@
+ALTER TABLE activities ADD COLUMN IF NOT EXISTS calories_source VARCHAR NOT NULL DEFAULT 'COMPUTED';
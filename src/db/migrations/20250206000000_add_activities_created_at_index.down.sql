@@ -0,0 +1 @@
+DROP INDEX IF EXISTS idx_activities_user_created_id;
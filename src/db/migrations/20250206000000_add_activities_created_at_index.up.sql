@@ -0,0 +1 @@
+CREATE UNIQUE INDEX IF NOT EXISTS idx_activities_user_created_id ON activities (user_id, created_at, activity_id);
@@ -0,0 +1,2 @@
+-- New address awaiting confirmation when email verification is required
+ALTER TABLE users ADD COLUMN IF NOT EXISTS pending_email VARCHAR;
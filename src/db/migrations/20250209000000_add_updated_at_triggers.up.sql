@@ -0,0 +1,17 @@
+-- Keep updated_at current on every UPDATE so handlers don't have to set it
+CREATE OR REPLACE FUNCTION set_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    NEW.updated_at = NOW();
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS users_set_updated_at ON users;
+CREATE TRIGGER users_set_updated_at
+    BEFORE UPDATE ON users
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+DROP TRIGGER IF EXISTS activities_set_updated_at ON activities;
+CREATE TRIGGER activities_set_updated_at
+    BEFORE UPDATE ON activities
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
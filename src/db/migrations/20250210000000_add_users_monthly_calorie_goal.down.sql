@@ -0,0 +1 @@
+ALTER TABLE users DROP COLUMN IF EXISTS monthly_calorie_goal;
@@ -0,0 +1,2 @@
+-- Optional calories-burned target per calendar month, used for goal trends
+ALTER TABLE users ADD COLUMN IF NOT EXISTS monthly_calorie_goal INTEGER;
@@ -0,0 +1,2 @@
+-- UTC offset, in seconds, of the submitted done_at; done_at itself stays a UTC instant
+ALTER TABLE activities ADD COLUMN IF NOT EXISTS done_at_offset INTEGER;
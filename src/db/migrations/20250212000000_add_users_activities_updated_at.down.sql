@@ -0,0 +1,3 @@
+DROP TRIGGER IF EXISTS activities_touch_user ON activities;
+DROP FUNCTION IF EXISTS touch_activities_updated_at();
+ALTER TABLE users DROP COLUMN IF EXISTS activities_updated_at;
@@ -0,0 +1,20 @@
+-- Last time any of the user's activities was inserted, updated or deleted, for conditional GETs on the
+-- activity list. A trigger keeps it current so deletions move it forward too.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS activities_updated_at TIMESTAMPTZ;
+
+UPDATE users SET activities_updated_at = latest.updated_at
+FROM (SELECT user_id, MAX(updated_at) AS updated_at FROM activities GROUP BY user_id) AS latest
+WHERE users.user_id = latest.user_id;
+
+CREATE OR REPLACE FUNCTION touch_activities_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE users SET activities_updated_at = NOW()
+    WHERE user_id = COALESCE(NEW.user_id, OLD.user_id);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS activities_touch_user ON activities;
+CREATE TRIGGER activities_touch_user
+    AFTER INSERT OR UPDATE OR DELETE ON activities
+    FOR EACH ROW EXECUTE FUNCTION touch_activities_updated_at();
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_activities_tags;
+ALTER TABLE activities DROP COLUMN IF EXISTS tags;
@@ -0,0 +1,4 @@
+-- Free-form, per-user labels on activities ("marathon-training", "rehab"); GIN index serves the ?tag= filter
+ALTER TABLE activities ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX IF NOT EXISTS idx_activities_tags ON activities USING GIN (tags);
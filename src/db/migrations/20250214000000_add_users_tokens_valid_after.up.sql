@@ -0,0 +1,2 @@
+-- Tokens issued before this are revoked; set on account creation and on every email change
+ALTER TABLE users ADD COLUMN IF NOT EXISTS tokens_valid_after TIMESTAMPTZ;
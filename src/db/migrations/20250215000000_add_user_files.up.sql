@@ -0,0 +1,8 @@
+-- Objects each user has uploaded. Keys are content hashes shared between users, so this is what
+-- decides who may check or share an object.
+CREATE TABLE IF NOT EXISTS user_files (
+    user_id UUID NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    file_key VARCHAR NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, file_key)
+);
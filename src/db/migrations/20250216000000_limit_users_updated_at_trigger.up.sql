@@ -0,0 +1,8 @@
+-- Only account and profile changes move users.updated_at. Bookkeeping writes (last_login_at,
+-- lifetime_calories, activities_updated_at, tokens_valid_after) leave it alone, so they don't
+-- invalidate conditional GETs or make every login look like a profile edit.
+DROP TRIGGER IF EXISTS users_set_updated_at ON users;
+CREATE TRIGGER users_set_updated_at
+    BEFORE UPDATE OF email, password, preference, weight_unit, height_unit, weight, height, name,
+        image_uri, timezone, verified, role, pending_email, monthly_calorie_goal ON users
+    FOR EACH ROW EXECUTE FUNCTION set_updated_at();
@@ -0,0 +1,16 @@
+DROP TRIGGER IF EXISTS activities_touch_user_insert ON activities;
+DROP TRIGGER IF EXISTS activities_touch_user_update ON activities;
+DROP TRIGGER IF EXISTS activities_touch_user_delete ON activities;
+
+CREATE OR REPLACE FUNCTION touch_activities_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE users SET activities_updated_at = NOW()
+    WHERE user_id = COALESCE(NEW.user_id, OLD.user_id);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS activities_touch_user ON activities;
+CREATE TRIGGER activities_touch_user
+    AFTER INSERT OR UPDATE OR DELETE ON activities
+    FOR EACH ROW EXECUTE FUNCTION touch_activities_updated_at();
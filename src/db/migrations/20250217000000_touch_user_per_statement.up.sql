@@ -0,0 +1,30 @@
+-- Touch users.activities_updated_at once per statement instead of once per activity row, so a bulk
+-- insert or import of N activities updates the user row once rather than N times. Postgres only
+-- allows transition tables on single-event triggers, hence three triggers sharing one function.
+CREATE OR REPLACE FUNCTION touch_activities_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE users SET activities_updated_at = NOW()
+    WHERE user_id IN (SELECT DISTINCT user_id FROM changed);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS activities_touch_user ON activities;
+
+DROP TRIGGER IF EXISTS activities_touch_user_insert ON activities;
+CREATE TRIGGER activities_touch_user_insert
+    AFTER INSERT ON activities
+    REFERENCING NEW TABLE AS changed
+    FOR EACH STATEMENT EXECUTE FUNCTION touch_activities_updated_at();
+
+DROP TRIGGER IF EXISTS activities_touch_user_update ON activities;
+CREATE TRIGGER activities_touch_user_update
+    AFTER UPDATE ON activities
+    REFERENCING NEW TABLE AS changed
+    FOR EACH STATEMENT EXECUTE FUNCTION touch_activities_updated_at();
+
+DROP TRIGGER IF EXISTS activities_touch_user_delete ON activities;
+CREATE TRIGGER activities_touch_user_delete
+    AFTER DELETE ON activities
+    REFERENCING OLD TABLE AS changed
+    FOR EACH STATEMENT EXECUTE FUNCTION touch_activities_updated_at();
@@ -1,9 +1,188 @@
-use sqlx::PgPool;
+pub mod retention;
+
 use std::env;
+use std::fmt::Debug;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use log::warn;
+
+// Runs `connect` up to `attempts` times, doubling the wait after each failure.
+// Returns the last error once the attempts are used up.
+pub async fn connect_with_retry<T, E, F, Fut>(attempts: u32, backoff: Duration, mut connect: F) -> Result<T, E>
+where
+    E: Debug,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let attempts = attempts.max(1);
+    let mut delay = backoff;
+    let mut attempt = 1;
+    loop {
+        match connect().await {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < attempts => {
+                warn!("Database connection attempt {}/{} failed: {:?}, retrying in {:?}", attempt, attempts, err, delay);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// Queries slower than SLOW_QUERY_MS (default 500) are logged with their label
+fn slow_query_threshold() -> Duration {
+    Duration::from_millis(
+        env::var("SLOW_QUERY_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(500),
+    )
+}
+
+/// Number of queries a request ran through `counted` or `timed`. One is stored in the request
+/// extensions and also made ambient for the handler future, so query helpers can count without
+/// being handed the request.
+#[derive(Clone, Default)]
+pub struct QueryCounter(Arc<AtomicUsize>);
+
+impl QueryCounter {
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+tokio::task_local! {
+    static QUERY_COUNTER: QueryCounter;
+}
+
+/// Runs a request future with `counter` as the ambient query counter.
+pub async fn with_query_counter<F: Future>(counter: QueryCounter, request: F) -> F::Output {
+    QUERY_COUNTER.scope(counter, request).await
+}
+
+// Per-request counts are logged in debug builds, or in release with LOG_QUERY_COUNT=true
+pub fn log_query_count_enabled() -> bool {
+    cfg!(debug_assertions) || env::var("LOG_QUERY_COUNT").map(|v| v == "true").unwrap_or(false)
+}
+
+/// Awaits a query future, counting it against the current request. Queries outside a request
+/// (startup, spawned tasks) are not counted.
+pub async fn counted<F: Future>(query: F) -> F::Output {
+    let _ = QUERY_COUNTER.try_with(|counter| counter.0.fetch_add(1, Ordering::Relaxed));
+    query.await
+}
+
+/// Awaits a query future and logs a warning when it takes longer than the slow-query threshold.
+pub async fn timed<F: Future>(label: &str, query: F) -> F::Output {
+    let started = Instant::now();
+    let output = counted(query).await;
+    let elapsed = started.elapsed();
+    if elapsed > slow_query_threshold() {
+        warn!("Slow query {} took {}ms", label, elapsed.as_millis());
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-pub async fn create_pool() -> PgPool {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    PgPool::connect(&database_url)
+    // Connector stub failing the first `failures` calls, counting every call
+    fn flaky_connector(failures: usize, calls: &AtomicUsize) -> impl FnMut() -> std::future::Ready<Result<&'static str, String>> + '_ {
+        move || {
+            let call = calls.fetch_add(1, Ordering::SeqCst);
+            std::future::ready(if call < failures { Err(format!("attempt {} refused", call + 1)) } else { Ok("pool") })
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_connector_succeeds() {
+        let calls = AtomicUsize::new(0);
+        let result = connect_with_retry(5, Duration::from_millis(1), flaky_connector(3, &calls)).await;
+        assert_eq!(result, Ok("pool"));
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn returns_the_last_error_once_attempts_run_out() {
+        let calls = AtomicUsize::new(0);
+        let result = connect_with_retry(3, Duration::from_millis(1), flaky_connector(10, &calls)).await;
+        assert_eq!(result, Err("attempt 3 refused".to_string()));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn zero_attempts_still_tries_once() {
+        let calls = AtomicUsize::new(0);
+        let result = connect_with_retry(0, Duration::from_millis(1), flaky_connector(0, &calls)).await;
+        assert_eq!(result, Ok("pool"));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn bookkeeping_writes_leave_users_updated_at_alone() {
+        let Some(pool) = crate::test_support::test_pool().await else { return };
+        let (user_id, _) = crate::test_support::create_user(&pool).await;
+        let updated_at = || async {
+            sqlx::query_scalar::<_, chrono::DateTime<chrono::Utc>>("SELECT updated_at FROM users WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        };
+        let created = updated_at().await;
+
+        sqlx::query("UPDATE users SET last_login_at = NOW(), lifetime_calories = lifetime_calories + 10, activities_updated_at = NOW(), tokens_valid_after = NOW() WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(updated_at().await, created);
+
+        sqlx::query("UPDATE users SET name = 'Renamed' WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(updated_at().await > created);
+
+        crate::test_support::delete_user(&pool, user_id).await;
+    }
+
+    #[tokio::test]
+    async fn activity_statements_touch_the_user() {
+        let Some(pool) = crate::test_support::test_pool().await else { return };
+        let (user_id, _) = crate::test_support::create_user(&pool).await;
+        let touched = || async {
+            sqlx::query_scalar::<_, Option<chrono::DateTime<chrono::Utc>>>("SELECT activities_updated_at FROM users WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        };
+        assert_eq!(touched().await, None);
+
+        sqlx::query(
+            "INSERT INTO activities (user_id, activity_type, done_at, duration_in_minutes, calories_burned, created_at, updated_at)
+            SELECT $1, 'Running', NOW() - n * INTERVAL '1 day', 30, 300, NOW(), NOW() FROM generate_series(1, 3) AS n",
+        )
+        .bind(user_id)
+        .execute(&pool)
         .await
-        .expect("Failed to connect to the database")
-}
\ No newline at end of file
+        .unwrap();
+        let inserted = touched().await.expect("insert touches the user");
+
+        sqlx::query("DELETE FROM activities WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(touched().await.unwrap() > inserted);
+
+        crate::test_support::delete_user(&pool, user_id).await;
+    }
+}
@@ -0,0 +1,72 @@
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use sqlx::PgPool;
+use std::env;
+use std::time::Duration;
+
+// Rows deleted per statement, so a large backlog never holds one long lock
+const PURGE_BATCH_SIZE: i64 = 1000;
+
+/// Days of activity history to keep (`ACTIVITY_RETENTION_DAYS`); None keeps everything
+pub fn activity_retention_days() -> Option<i64> {
+    env::var("ACTIVITY_RETENTION_DAYS")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|value| *value > 0)
+}
+
+// ACTIVITY_RETENTION_INTERVAL_SECONDS, default daily
+fn retention_interval() -> Duration {
+    Duration::from_secs(
+        env::var("ACTIVITY_RETENTION_INTERVAL_SECONDS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|value| *value > 0)
+            .unwrap_or(24 * 60 * 60),
+    )
+}
+
+/// Deletes activities done before `cutoff` in batches and returns how many were removed.
+/// `lifetime_calories` is left as is: it is an all-time total, not a sum over retained rows.
+pub async fn purge_old_activities(pool: &PgPool, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+    let mut purged = 0;
+    loop {
+        let deleted = sqlx::query!(
+            "DELETE FROM activities WHERE activity_id IN (
+                SELECT activity_id FROM activities WHERE done_at < $1 LIMIT $2
+            )",
+            cutoff,
+            PURGE_BATCH_SIZE
+        )
+        .execute(pool)
+        .await?
+        .rows_affected();
+
+        purged += deleted;
+        if deleted < PURGE_BATCH_SIZE as u64 {
+            return Ok(purged);
+        }
+    }
+}
+
+/// Starts the periodic purge when `ACTIVITY_RETENTION_DAYS` is set. Failures are logged and the
+/// next run tries again.
+pub fn spawn_retention_job(pool: PgPool) {
+    let Some(days) = activity_retention_days() else {
+        return;
+    };
+    let interval = retention_interval();
+    info!("Purging activities older than {} days every {:?}", days, interval);
+
+    actix_web::rt::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let cutoff = Utc::now() - chrono::Duration::days(days);
+            match purge_old_activities(&pool, cutoff).await {
+                Ok(purged) => info!("Activity retention purged {} activities done before {}", purged, cutoff.to_rfc3339()),
+                Err(err) => error!("Activity retention run failed: {:?}", err),
+            }
+        }
+    });
+}
@@ -1,14 +1,26 @@
-use actix_web::{HttpResponse, ResponseError};
+use actix_web::{error::{JsonPayloadError, QueryPayloadError}, http::header, web, HttpRequest, HttpResponse, ResponseError};
+use serde::de::DeserializeOwned;
 use serde::Serialize;
+use crate::utils::window_counter::Quota;
 use std::fmt;
 
 #[derive(Debug)]
 pub enum AppError {
     NotFound(String),
     Unauthorized(String),
+    // 401 challenging for HTTP Basic credentials in the given realm rather than a bearer token
+    BasicUnauthorized(String, &'static str),
+    Forbidden(String),
     Conflict(String),
     InternalServerError(String),
     BadRequest(String),
+    TooManyRequests(String),
+    // 429 with Retry-After and X-RateLimit-* headers from the exhausted quota
+    RateLimited(String, Quota),
+    RequestTimeout(String),
+    ServiceUnavailable(String),
+    RequestHeaderFieldsTooLarge(String),
+    RangeNotSatisfiable(String),
 }
 
 #[derive(Serialize)]
@@ -21,9 +33,17 @@ impl fmt::Display for AppError {
         match self {
             AppError::NotFound(msg) => write!(f, "Not Found: {}", msg),
             AppError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            AppError::BasicUnauthorized(msg, _) => write!(f, "Unauthorized: {}", msg),
+            AppError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
             AppError::Conflict(msg) => write!(f, "Conflict: {}", msg),
             AppError::InternalServerError(msg) => write!(f, "Internal Server Error: {}", msg),
             AppError::BadRequest(msg) => write!(f, "Bad Request: {}", msg),
+            AppError::TooManyRequests(msg) => write!(f, "Too Many Requests: {}", msg),
+            AppError::RateLimited(msg, _) => write!(f, "Too Many Requests: {}", msg),
+            AppError::RequestTimeout(msg) => write!(f, "Request Timeout: {}", msg),
+            AppError::ServiceUnavailable(msg) => write!(f, "Service Unavailable: {}", msg),
+            AppError::RequestHeaderFieldsTooLarge(msg) => write!(f, "Request Header Fields Too Large: {}", msg),
+            AppError::RangeNotSatisfiable(msg) => write!(f, "Range Not Satisfiable: {}", msg),
         }
     }
 }
@@ -32,10 +52,106 @@ impl ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
         match self {
             AppError::NotFound(msg) => HttpResponse::NotFound().json(ErrorResponse { error: msg.clone() }),
-            AppError::Unauthorized(msg) => HttpResponse::Unauthorized().json(ErrorResponse { error: msg.clone() }),
+            AppError::Unauthorized(msg) => HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+                .json(ErrorResponse { error: msg.clone() }),
+            AppError::BasicUnauthorized(msg, realm) => HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, format!("Basic realm=\"{}\"", realm)))
+                .json(ErrorResponse { error: msg.clone() }),
+            AppError::Forbidden(msg) => HttpResponse::Forbidden().json(ErrorResponse { error: msg.clone() }),
             AppError::Conflict(msg) => HttpResponse::Conflict().json(ErrorResponse { error: msg.clone() }),
             AppError::InternalServerError(msg) => HttpResponse::InternalServerError().json(ErrorResponse { error: msg.clone() }),
             AppError::BadRequest(msg) => HttpResponse::BadRequest().json(ErrorResponse { error: msg.clone() }),
+            AppError::TooManyRequests(msg) => HttpResponse::TooManyRequests().json(ErrorResponse { error: msg.clone() }),
+            AppError::RateLimited(msg, quota) => {
+                let mut response = HttpResponse::TooManyRequests();
+                response.insert_header((header::RETRY_AFTER, quota.reset_after.to_string()));
+                for quota_header in quota.headers() {
+                    response.insert_header(quota_header);
+                }
+                response.json(ErrorResponse { error: msg.clone() })
+            }
+            AppError::RequestTimeout(msg) => HttpResponse::RequestTimeout().json(ErrorResponse { error: msg.clone() }),
+            AppError::ServiceUnavailable(msg) => HttpResponse::ServiceUnavailable().json(ErrorResponse { error: msg.clone() }),
+            AppError::RequestHeaderFieldsTooLarge(msg) => HttpResponse::RequestHeaderFieldsTooLarge().json(ErrorResponse { error: msg.clone() }),
+            AppError::RangeNotSatisfiable(msg) => HttpResponse::RangeNotSatisfiable().json(ErrorResponse { error: msg.clone() }),
         }
     }
-}
\ No newline at end of file
+}
+
+/// `QueryConfig` error handler turning query string parse failures into a JSON 400. The parser's
+/// error does not say which parameter failed, so the query is re-parsed one parameter at a time
+/// and the first one that breaks it is named.
+pub fn query_error_handler<T: DeserializeOwned>(err: QueryPayloadError, req: &HttpRequest) -> actix_web::Error {
+    let pairs: Vec<&str> = req.query_string().split('&').filter(|pair| !pair.is_empty()).collect();
+    let culprit = (0..pairs.len())
+        .find(|end| web::Query::<T>::from_query(&pairs[..=*end].join("&")).is_err())
+        .map(|end| pairs[end].split('=').next().unwrap_or_default());
+
+    let message = match culprit {
+        Some(name) => format!("Invalid value for query parameter {}", name),
+        None => format!("Invalid query string: {}", err),
+    };
+    AppError::BadRequest(message).into()
+}
+
+/// `JsonConfig` error handler returning field-level deserialization failures (such as a
+/// fractional duration) as a JSON 400 with serde's message, minus the line/column suffix.
+/// Other payload errors keep actix's default response.
+pub fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::Deserialize(err) if err.is_data() => {
+            let message = err.to_string();
+            let message = message.rsplit_once(" at line ").map_or(message.as_str(), |(head, _)| head);
+            AppError::BadRequest(message.to_string()).into()
+        }
+        err => err.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    #[allow(dead_code)]
+    enum Mode {
+        Atomic,
+        Partial,
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct ListQuery {
+        limit: Option<i64>,
+        mode: Option<Mode>,
+    }
+
+    async fn query_error_message(query: &str) -> String {
+        let req = TestRequest::get().uri(&format!("/list?{}", query)).to_http_request();
+        let err = web::Query::<ListQuery>::from_query(query).err().expect("query should not parse");
+        let response = query_error_handler::<ListQuery>(err, &req).error_response();
+        assert_eq!(response.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()["error"].as_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn names_the_parameter_with_a_bad_integer() {
+        assert_eq!(
+            query_error_message("mode=atomic&limit=ten").await,
+            "Invalid value for query parameter limit"
+        );
+    }
+
+    #[tokio::test]
+    async fn names_the_parameter_with_an_unknown_enum_value() {
+        assert_eq!(
+            query_error_message("limit=10&mode=sideways").await,
+            "Invalid value for query parameter mode"
+        );
+    }
+}
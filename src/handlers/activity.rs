@@ -1,11 +1,27 @@
-use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use actix_web::{web, http::header::{self, HttpDate}, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder};
+use actix_multipart::Multipart;
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
-use crate::models::{activity::Activity, activity::GetActivityCreatedAt, user::GetUserId};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, SubsecRound, Utc};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::time::{Duration, SystemTime};
+use log::{error, warn};
+use tokio::sync::mpsc;
+use actix_web::rt::task::spawn_blocking;
+use sqlx::{Acquire, Postgres, QueryBuilder};
+use lazy_static::lazy_static;
+use crate::models::{activity::Activity, activity::GetActivityForUpdate, user::GetUserActivityListState, user::GetUserId, user::GetUserWeight};
 use crate::errors::AppError;
+use crate::db::{counted, timed};
+use crate::handlers::admin::require_admin;
 use crate::utils::jwt::Claims;
+use crate::utils::window_counter::{Reservation, WindowCounter};
+use crate::utils::validation::{clamp_pagination, deserialize_whole_minutes, validate_batch_size};
+use crate::utils::timezone::resolve_timezone;
+use crate::utils::activity_types::{activity_category, ActivityTypeInfo, activity_met, activity_types_in_category, find_activity_type, resolve_activity_alias, ACTIVITY_TYPES};
 
 #[derive(Deserialize, Validate)]
 #[serde(rename_all = "camelCase")]
@@ -20,7 +36,16 @@ pub struct ActivityRequest {
 
     #[validate(required(message = "Duration is required"))]
     #[validate(range(min = 1, message = "Duration must be at least 1 minute"))]
+    #[serde(default, deserialize_with = "deserialize_whole_minutes")]
     duration_in_minutes: Option<i32>,
+
+    // Optional device-reported value overriding the formula
+    #[validate(range(min = 0, max = 10000, message = "Calories burned must be between 0 and 10000"))]
+    calories_burned: Option<i32>,
+
+    // Optional labels; each tag is checked in normalize_tags
+    #[validate(length(max = 10, message = "At most 10 tags are allowed"))]
+    tags: Option<Vec<String>>,
 }
 
 #[derive(Serialize)]
@@ -28,32 +53,554 @@ pub struct ActivityRequest {
 pub struct ActivityResponse {
     activity_id: Uuid,
     activity_type: String,
+    category: &'static str,
+    met: Option<f64>,
     done_at: String,
+    // done_at in the offset it was submitted with
+    done_at_local: Option<String>,
     duration_in_minutes: i32,
     calories_burned: i32,
+    calories_source: String,
     created_at: String,
     updated_at: String,
+    tags: Vec<String>,
 }
 
-#[derive(Deserialize)]
+// Renders a stored UTC instant back in the client's original offset
+fn done_at_local(done_at: DateTime<Utc>, offset: Option<i32>) -> Option<String> {
+    offset
+        .and_then(FixedOffset::east_opt)
+        .map(|offset| done_at.with_timezone(&offset).to_rfc3339())
+}
+
+impl From<Activity> for ActivityResponse {
+    fn from(activity: Activity) -> Self {
+        ActivityResponse {
+            activity_id: activity.activity_id,
+            category: activity_category(&activity.activity_type),
+            met: activity_met(&activity.activity_type),
+            activity_type: activity.activity_type,
+            done_at: activity.done_at.to_rfc3339(),
+            done_at_local: done_at_local(activity.done_at, activity.done_at_offset),
+            duration_in_minutes: activity.duration_in_minutes,
+            calories_burned: activity.calories_burned,
+            calories_source: activity.calories_source,
+            created_at: activity.created_at.to_rfc3339(),
+            updated_at: activity.updated_at.to_rfc3339(),
+            tags: activity.tags,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct GetActivitiesQuery {
-    limit: Option<i32>,
-    offset: Option<i32>,
+    limit: Option<i64>,
+    offset: Option<i64>,
     activity_type: Option<String>,
     done_at_from: Option<String>,
     done_at_to: Option<String>,
     calories_burned_min: Option<i32>,
     calories_burned_max: Option<i32>,
+    cursor: Option<String>,
+    preference_filter: Option<bool>,
+    // Columnar response, see CompactActivityListResponse
+    compact: Option<bool>,
+    // Wraps offset pages as { data, meta } instead of a bare array; cursor pages always are
+    envelope: Option<bool>,
+    // Comma-separated tags; tagMatch=any (default) or all
+    tag: Option<String>,
+    tag_match: Option<String>,
+    // Local calendar day (YYYY-MM-DD), interpreted in `tz` or the profile timezone
+    #[serde(alias = "done_on")]
+    done_on: Option<String>,
+    tz: Option<String>,
+    #[serde(skip)]
+    done_on_range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    // Resolved from the user's preference when preferenceFilter=true, never read from the query
+    #[serde(skip)]
+    preference_types: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+pub struct RecentActivitiesQuery {
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAllQuery {
+    confirm: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportQuery {
+    strict: Option<bool>,
+    unknown_type_policy: Option<String>,
+}
+
+// What an import does with a row whose activity type is not configured
+#[derive(Clone, Copy, PartialEq)]
+enum UnknownTypePolicy {
+    // Report the row as an error (the default)
+    Reject,
+    // Leave the row out without an error
+    Skip,
+    // Translate through ACTIVITY_TYPE_ALIASES, rejecting types without an alias
+    Map,
+}
+
+impl UnknownTypePolicy {
+    fn parse(value: Option<&str>) -> Result<Self, AppError> {
+        match value {
+            None | Some("reject") => Ok(UnknownTypePolicy::Reject),
+            Some("skip") => Ok(UnknownTypePolicy::Skip),
+            Some("map") => Ok(UnknownTypePolicy::Map),
+            Some(_) => Err(AppError::BadRequest("Unknown type policy must be one of reject, skip or map".to_string())),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportTypeSkip {
+    row: usize,
+    activity_type: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportTypeMapping {
+    row: usize,
+    from: String,
+    to: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportRowError {
+    row: usize,
+    error: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportResponse {
+    imported: usize,
+    skipped: usize,
+    errors: Vec<ImportRowError>,
+    // Rows left out by unknownTypePolicy=skip, also counted in `skipped`
+    unknown_types: Vec<ImportTypeSkip>,
+    // Rows imported under an aliased type by unknownTypePolicy=map
+    mapped_types: Vec<ImportTypeMapping>,
+}
+
+#[derive(Deserialize)]
+pub struct BulkCreateQuery {
+    mode: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkItemResult {
+    index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    activity_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkCreateResponse {
+    created: usize,
+    failed: usize,
+    results: Vec<BulkItemResult>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityTypeResponse {
+    activity_type: &'static str,
+    label: &'static str,
+    icon: &'static str,
+    category: &'static str,
+    calories_per_minute: i32,
+    met: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalorieBreakdownResponse {
+    activity_id: Uuid,
+    activity_type: String,
+    duration_in_minutes: i32,
+    // COMPUTED from the formula, or USER when the client supplied the value
+    calories_source: String,
+    formula: &'static str,
+    calories_per_minute: Option<i32>,
+    met: Option<f64>,
+    rounding: &'static str,
+    // What the formula gives with the current activity type registry
+    computed_calories: Option<i32>,
+    calories_burned: i32,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaginationMeta {
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ActivityListResponse {
+    data: Vec<ActivityResponse>,
+    meta: PaginationMeta,
+}
+
+// Serialized ActivityResponse keys, in the order compact rows list them
+const ACTIVITY_COLUMNS: &[&str] = &[
+    "activityId",
+    "activityType",
+    "category",
+    "met",
+    "doneAt",
+    "doneAtLocal",
+    "durationInMinutes",
+    "caloriesBurned",
+    "caloriesSource",
+    "createdAt",
+    "updatedAt",
+    "tags",
+];
+
+// ?compact=true form of a list page: field names once in `columns`, then one array per activity
+#[derive(Serialize)]
+pub struct CompactActivityListResponse {
+    columns: &'static [&'static str],
+    rows: Vec<Vec<serde_json::Value>>,
+    meta: PaginationMeta,
+}
+
+impl TryFrom<ActivityListResponse> for CompactActivityListResponse {
+    type Error = serde_json::Error;
+
+    // Goes through the verbose serialization so both forms always carry the same values
+    fn try_from(page: ActivityListResponse) -> Result<Self, Self::Error> {
+        let rows = page.data
+            .iter()
+            .map(|activity| {
+                let mut object = match serde_json::to_value(activity)? {
+                    serde_json::Value::Object(object) => object,
+                    _ => serde_json::Map::new(),
+                };
+                Ok(ACTIVITY_COLUMNS
+                    .iter()
+                    .map(|column| object.remove(*column).unwrap_or(serde_json::Value::Null))
+                    .collect())
+            })
+            .collect::<Result<_, serde_json::Error>>()?;
+
+        Ok(CompactActivityListResponse { columns: ACTIVITY_COLUMNS, rows, meta: page.meta })
+    }
+}
+
+// Mirrors meta.total for clients that read the count from headers (e.g. react-admin)
+pub const TOTAL_COUNT_HEADER: &str = "X-Total-Count";
+
+const CALORIES_SOURCE_COMPUTED: &str = "COMPUTED";
+const CALORIES_SOURCE_USER: &str = "USER";
+
+const DEFAULT_RECENT_LIMIT: i64 = 5;
+const MAX_RECENT_LIMIT: i64 = 50;
+
+// Upper bound on the raw CSV body, independent of the row cap
+const MAX_IMPORT_BYTES: usize = 1024 * 1024;
+
+// Applies a delta to the user's cached lifetime calories inside the caller's transaction
+async fn adjust_lifetime_calories(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    delta: i64,
+) -> Result<(), AppError> {
+    if delta == 0 {
+        return Ok(());
+    }
+
+    counted(sqlx::query!(
+        "UPDATE users SET lifetime_calories = lifetime_calories + $1 WHERE user_id = $2",
+        delta,
+        user_id
+    )
+    .execute(&mut **tx))
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    Ok(())
+}
+
+// Sample history for demo accounts: (activity type, days ago, duration in minutes)
+const DEMO_ACTIVITIES: &[(&str, i64, i32)] = &[
+    ("Walking", 1, 30),
+    ("Running", 2, 25),
+    ("Yoga", 3, 45),
+    ("Cycling", 5, 40),
+    ("HIIT", 6, 20),
+];
+
+/// Inserts the demo activities for a freshly registered user inside the caller's transaction.
+/// Types missing from the configured registry are skipped.
+pub async fn seed_demo_activities(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<(), AppError> {
+    let now = Utc::now();
+    let mut total_calories: i64 = 0;
+
+    for (activity_type, days_ago, duration) in DEMO_ACTIVITIES {
+        let Ok(calories_burned) = calculate_calories_burned(activity_type, *duration) else {
+            continue;
+        };
+
+        sqlx::query!(
+            "INSERT INTO activities (activity_id, user_id, activity_type, done_at, duration_in_minutes, calories_burned, calories_source, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            Uuid::now_v7(),
+            user_id,
+            activity_type,
+            now - chrono::Duration::days(*days_ago),
+            duration,
+            calories_burned,
+            CALORIES_SOURCE_COMPUTED,
+            now,
+            now
+        )
+        .execute(&mut **tx)
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+        total_calories += calories_burned as i64;
+    }
+
+    adjust_lifetime_calories(tx, user_id, total_calories).await
 }
 
 // Helper function to calculate calories burned
 fn calculate_calories_burned(activity_type: &str, duration: i32) -> Result<i32, AppError> {
-    match activity_type {
-        "Walking" | "Yoga" | "Stretching" => Ok(4 * duration),
-        "Cycling" | "Swimming" | "Dancing" => Ok(8 * duration),
-        "Hiking" | "Running" | "HIIT" | "JumpRope" => Ok(10 * duration),
-        _ => Err(AppError::BadRequest("Invalid activity type".to_string())),
+    let info = find_activity_type(activity_type)
+        .ok_or_else(|| AppError::BadRequest("Invalid activity type".to_string()))?;
+    info.calories_per_minute
+        .checked_mul(duration)
+        .ok_or_else(|| AppError::BadRequest("Duration is too long".to_string()))
+}
+
+const MAX_TAG_LENGTH: usize = 32;
+
+// Trims and lowercases tags so filters match regardless of how they were typed, dropping
+// duplicates but keeping the submitted order
+fn normalize_tags<'a>(tags: impl IntoIterator<Item = &'a str>) -> Result<Vec<String>, AppError> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() {
+            return Err(AppError::BadRequest("Tags cannot be empty".to_string()));
+        }
+        if tag.chars().count() > MAX_TAG_LENGTH {
+            return Err(AppError::BadRequest(format!("Tags cannot exceed {} characters", MAX_TAG_LENGTH)));
+        }
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    Ok(normalized)
+}
+
+// When enabled, a formula result of zero is treated as a misconfigured multiplier rather than stored.
+// Client-supplied zeros are still accepted.
+pub fn reject_zero_calories() -> bool {
+    env::var("REJECT_ZERO_CALORIES").map(|v| v == "true").unwrap_or(false)
+}
+
+// Uses the client-supplied calories when present, otherwise the formula. The activity type and
+// its calorie cap are validated either way.
+fn resolve_calories(payload: &ActivityRequest) -> Result<(i32, &'static str), AppError> {
+    resolve_calories_for(
+        payload.activity_type.as_ref().unwrap(),
+        payload.duration_in_minutes.unwrap(),
+        payload.calories_burned,
+    )
+}
+
+fn resolve_calories_for(activity_type: &str, duration: i32, supplied: Option<i32>) -> Result<(i32, &'static str), AppError> {
+    let info = find_activity_type(activity_type)
+        .ok_or_else(|| AppError::BadRequest("Invalid activity type".to_string()))?;
+    resolve_calories_with(info, duration, supplied, reject_zero_calories())
+}
+
+// resolve_calories_for against one registry entry, rejecting values above its optional
+// maxCalories whether supplied or computed
+fn resolve_calories_with(
+    info: &ActivityTypeInfo,
+    duration: i32,
+    supplied: Option<i32>,
+    reject_zero: bool,
+) -> Result<(i32, &'static str), AppError> {
+    let computed = info.calories_per_minute
+        .checked_mul(duration)
+        .ok_or_else(|| AppError::BadRequest("Duration is too long".to_string()))?;
+
+    let resolved = match supplied {
+        Some(calories) => (calories, CALORIES_SOURCE_USER),
+        None if computed == 0 && reject_zero => {
+            error!("Activity type {} computed zero calories, check its caloriesPerMinute", info.name);
+            return Err(AppError::InternalServerError("Calorie configuration error".to_string()));
+        }
+        None => (computed, CALORIES_SOURCE_COMPUTED),
+    };
+    if let Some(max) = info.max_calories.filter(|max| resolved.0 > *max) {
+        return Err(AppError::BadRequest(format!("Calories for {} cannot exceed {}", info.name, max)));
+    }
+    Ok(resolved)
+}
+
+// GET /v1/activity/types
+pub async fn get_activity_types() -> HttpResponse {
+    let types: Vec<ActivityTypeResponse> = ACTIVITY_TYPES
+        .iter()
+        .map(|info| ActivityTypeResponse {
+            activity_type: &info.name,
+            label: &info.label,
+            icon: &info.icon,
+            category: &info.category,
+            calories_per_minute: info.calories_per_minute,
+            met: info.met(),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(types)
+}
+
+// When enabled, activities can only be logged once the profile has a weight. Calories are still
+// flat per-minute, so this is the whole check until a weight-aware calorie mode exists.
+pub fn require_weight_for_activity() -> bool {
+    env::var("REQUIRE_WEIGHT_FOR_ACTIVITY").map(|v| v == "true").unwrap_or(false)
+}
+
+fn check_weight_for_activity(weight: Option<f64>, required: bool) -> Result<(), AppError> {
+    if required && weight.is_none() {
+        return Err(AppError::BadRequest("Set your weight in your profile first".to_string()));
+    }
+    Ok(())
+}
+
+// Unlimited unless MAX_ACTIVITIES_PER_HOUR is set
+pub fn max_activities_per_window() -> Option<u32> {
+    env::var("MAX_ACTIVITIES_PER_HOUR")
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .filter(|value| *value > 0)
+}
+
+// Length of the creation window, one hour unless ACTIVITY_RATE_WINDOW_SECONDS overrides it
+pub fn activity_rate_window() -> Duration {
+    Duration::from_secs(
+        env::var("ACTIVITY_RATE_WINDOW_SECONDS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|value| *value > 0)
+            .unwrap_or(60 * 60),
+    )
+}
+
+lazy_static! {
+    // Activities created per user, the window starts at the first creation
+    static ref ACTIVITY_CREATIONS: WindowCounter<Uuid> = WindowCounter::new(activity_rate_window());
+}
+
+// Reserves `count` creations in the user's window before anything is written, so concurrent
+// requests cannot all pass the check. None when no limit is configured.
+fn reserve_activity_creations(user_id: Uuid, count: usize) -> Result<Option<Reservation>, AppError> {
+    let Some(max) = max_activities_per_window() else {
+        return Ok(None);
+    };
+
+    let count = u32::try_from(count).unwrap_or(u32::MAX);
+    ACTIVITY_CREATIONS.reserve(user_id, count, max)
+        .map(Some)
+        .map_err(|quota| AppError::RateLimited("Too many activities created, try again later".to_string(), quota))
+}
+
+// Keeps the slots of the activities actually stored and adds the quota headers. A reservation
+// dropped without this (any early error) hands its slots back.
+fn commit_activity_creations(response: &mut HttpResponseBuilder, reservation: Option<Reservation>, created: usize) {
+    if let Some(reservation) = reservation {
+        for quota_header in reservation.commit(created as u32).headers() {
+            response.insert_header(quota_header);
+        }
+    }
+}
+
+// When enabled, a user can log at most one activity per local calendar day
+pub fn one_activity_per_day() -> bool {
+    env::var("ONE_ACTIVITY_PER_DAY").map(|v| v == "true").unwrap_or(false)
+}
+
+// Rejects the create when the user already has an activity on the local day of `done_at`, in the
+// profile timezone, and otherwise returns that day. The user row is locked first so concurrent
+// creates for the same day serialize.
+async fn ensure_day_is_free(
+    tx: &mut sqlx::Transaction<'_, Postgres>,
+    user_id: Uuid,
+    done_at: DateTime<Utc>,
+) -> Result<NaiveDate, AppError> {
+    let saved_timezone = counted(sqlx::query_scalar!(
+        "SELECT timezone FROM users WHERE user_id = $1 FOR UPDATE",
+        user_id
+    )
+    .fetch_one(&mut **tx))
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    let timezone = resolve_timezone(None, saved_timezone.as_deref())?;
+    let day = timezone.local_date(done_at);
+    let (from, to) = timezone.day_bounds_utc(day);
+    let taken = counted(sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM activities WHERE user_id = $1 AND done_at >= $2 AND done_at < $3) AS "taken!""#,
+        user_id,
+        from,
+        to
+    )
+    .fetch_one(&mut **tx))
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    if taken {
+        return Err(AppError::Conflict(format!("An activity is already logged on {}", day)));
+    }
+    Ok(day)
+}
+
+// The opt-in rules every way of creating activities enforces
+#[derive(Clone, Copy)]
+struct CreationRules {
+    one_per_day: bool,
+    require_weight: bool,
+}
+
+impl CreationRules {
+    fn from_env() -> Self {
+        CreationRules {
+            one_per_day: one_activity_per_day(),
+            require_weight: require_weight_for_activity(),
+        }
     }
 }
 
@@ -66,58 +613,90 @@ pub async fn create_activity(
     // Validate payload
     payload.validate().map_err(|err| AppError::BadRequest(err.to_string()))?;
 
-    let extensions = req.extensions();
-    let claims = extensions.get::<Claims>().unwrap();
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
 
     // Fetch user from database
-    let user = sqlx::query_as!(
-        GetUserId,
-        "SELECT user_id FROM users WHERE email = $1",
-        claims.sub
+    let user = counted(sqlx::query_as!(
+        GetUserWeight,
+        "SELECT user_id, weight FROM users WHERE email = $1",
+        email
     )
-    .fetch_optional(&**pool)
+    .fetch_optional(&**pool))
     .await
     .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
     .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
 
-    // Parse done_at date
-    let done_at = DateTime::parse_from_rfc3339(&payload.done_at.as_ref().unwrap())
-        .map_err(|_| AppError::BadRequest("Invalid date format".to_string()))?
-        .with_timezone(&Utc);
+    let rules = CreationRules::from_env();
+    check_weight_for_activity(user.weight, rules.require_weight)?;
+    let reservation = reserve_activity_creations(user.user_id, 1)?;
 
-    // Calculate calories burned
-    let calories_burned = calculate_calories_burned(
-        payload.activity_type.as_ref().unwrap(),
-        payload.duration_in_minutes.unwrap(),
-    )?;
+    // Parse done_at date, keeping the submitted offset for doneAtLocal
+    let submitted_done_at = parse_done_at(payload.done_at.as_ref().unwrap())
+        .map_err(|msg| AppError::BadRequest(msg.to_string()))?;
+    let done_at = submitted_done_at.with_timezone(&Utc);
+    let done_at_offset = submitted_done_at.offset().local_minus_utc();
+
+    // Calculate calories burned, unless the client supplied its own value
+    let (calories_burned, calories_source) = resolve_calories(&payload)?;
+    let tags = normalize_tags(payload.tags.iter().flatten().map(String::as_str))?;
 
-    // Insert activity into database
-    let activity_id = Uuid::new_v4();
+    // Insert activity into database, keeping the lifetime counter in the same transaction
+    // Time-ordered ids keep inserts at the right edge of the primary key index
+    let activity_id = spawn_blocking(Uuid::now_v7)
+        .await
+        .map_err(|_| AppError::InternalServerError("UUID generation failed".to_string()))?;
     let now = Utc::now();
-    sqlx::query!(
-        "INSERT INTO activities (activity_id, user_id, activity_type, done_at, duration_in_minutes, calories_burned, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+    let mut tx = pool.begin()
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    if rules.one_per_day {
+        ensure_day_is_free(&mut tx, user.user_id, done_at).await?;
+    }
+
+    counted(sqlx::query!(
+        "INSERT INTO activities (activity_id, user_id, activity_type, done_at, done_at_offset, duration_in_minutes, calories_burned, calories_source, created_at, updated_at, tags) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
         activity_id,
         user.user_id,
         payload.activity_type.as_ref().unwrap(),
         done_at,
+        done_at_offset,
         payload.duration_in_minutes.unwrap(),
         calories_burned,
+        calories_source,
+        now,
         now,
-        now
+        &tags
     )
-    .execute(&**pool)
+    .execute(&mut *tx))
     .await
     .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
 
+    adjust_lifetime_calories(&mut tx, user.user_id, calories_burned as i64).await?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
     // Return response
-    Ok(HttpResponse::Created().json(ActivityResponse {
+    let mut response = HttpResponse::Created();
+    commit_activity_creations(&mut response, reservation, 1);
+    Ok(response.json(ActivityResponse {
         activity_id,
         activity_type: payload.activity_type.clone().unwrap(),
-        done_at: payload.done_at.clone().unwrap(),
+        category: activity_category(payload.activity_type.as_ref().unwrap()),
+        met: activity_met(payload.activity_type.as_ref().unwrap()),
+        done_at: done_at.to_rfc3339(),
+        done_at_local: Some(submitted_done_at.to_rfc3339()),
         duration_in_minutes: payload.duration_in_minutes.unwrap(),
         calories_burned,
+        calories_source: calories_source.to_string(),
         created_at: now.to_rfc3339(),
         updated_at: now.to_rfc3339(),
+        tags,
     }))
 }
 
@@ -127,14 +706,17 @@ pub async fn get_activities(
     pool: web::Data<sqlx::PgPool>,
     query: web::Query<GetActivitiesQuery>,
 ) -> Result<HttpResponse, AppError> {
-    let extensions = req.extensions();
-    let claims = extensions.get::<Claims>().unwrap();
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
 
-    // Fetch user from database
+    // Fetch user from database. The list also depends on the profile (preference, timezone), so
+    // it counts as modified when either the activities or the profile last changed.
     let user = sqlx::query_as!(
-        GetUserId,
-        "SELECT user_id FROM users WHERE email = $1",
-        claims.sub
+        GetUserActivityListState,
+        r#"SELECT user_id, timezone, GREATEST(activities_updated_at, updated_at) AS "last_modified!" FROM users WHERE email = $1"#,
+        email
     )
     .fetch_optional(&**pool)
     .await
@@ -145,134 +727,581 @@ pub async fn get_activities(
     })?
     .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
 
-    // Build query
-    let limit = query.limit.unwrap_or(5);
-    let offset = query.offset.unwrap_or(0);
-    let mut sql_query = "SELECT * FROM activities WHERE user_id = $1".to_string();
-    let mut params: Vec<String> = vec![user.user_id.to_string()];
-
-    if let Some(activity_type) = &query.activity_type {
-        sql_query.push_str(" AND activity_type = $2");
-        params.push(activity_type.clone());
+    // HTTP dates have whole-second precision, so compare at that granularity
+    let last_modified = HttpDate::from(SystemTime::from(user.last_modified.trunc_subsecs(0)));
+    if let Some(header::IfModifiedSince(since)) = req.get_header::<header::IfModifiedSince>() {
+        if last_modified <= since {
+            return Ok(HttpResponse::NotModified()
+                .insert_header(header::LastModified(last_modified))
+                .finish());
+        }
     }
 
-    if let Some(done_at_from) = &query.done_at_from {
-        sql_query.push_str(" AND done_at >= $3::timestamptz");
-        params.push(done_at_from.clone());
-    }
+    let mut query = query.into_inner();
 
-    if let Some(done_at_to) = &query.done_at_to {
-        sql_query.push_str(" AND done_at <= $4::timestamptz");
-        params.push(done_at_to.clone());
+    // Translate the local calendar day into a UTC range
+    if let Some(done_on) = &query.done_on {
+        let date = NaiveDate::parse_from_str(done_on, "%Y-%m-%d")
+            .map_err(|_| AppError::BadRequest("Invalid date format, expected YYYY-MM-DD".to_string()))?;
+        let timezone = resolve_timezone(query.tz.as_deref(), user.timezone.as_deref())?;
+        query.done_on_range = Some(timezone.day_bounds_utc(date));
     }
 
-    if let Some(calories_burned_min) = query.calories_burned_min {
-        sql_query.push_str(" AND calories_burned >= $5");
-        params.push(calories_burned_min.to_string());
-    }
+    // Narrow to the types in the user's preferred category; no preference means no narrowing
+    if query.preference_filter == Some(true) {
+        let preference = sqlx::query_scalar!(
+            "SELECT preference FROM users WHERE user_id = $1",
+            user.user_id
+        )
+        .fetch_one(&**pool)
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
 
-    if let Some(calories_burned_max) = query.calories_burned_max {
-        sql_query.push_str(" AND calories_burned <= $6");
-        params.push(calories_burned_max.to_string());
+        query.preference_types = preference.map(|category| activity_types_in_category(&category));
     }
 
-    sql_query.push_str(" LIMIT $7 OFFSET $8");
-    params.push(limit.to_string());
-    params.push(offset.to_string());
+    let (limit, offset) = clamp_pagination(query.limit, query.offset);
+    let page = fetch_activity_page(&pool, user.user_id, &query, PageOrder::Configured, limit, offset).await?;
 
-    // Fetch activities for the user
-    let activities = sqlx::query_as::<_, Activity>(&sql_query)
-        .bind(&user.user_id)
-        .bind(&query.activity_type)
-        .bind(&query.done_at_from)
-        .bind(&query.done_at_to)
-        .bind(&query.calories_burned_min)
-        .bind(&query.calories_burned_max)
-        .bind(limit)
-        .bind(offset)
-        .fetch_all(&**pool)
-        .await
-        .map_err(|e| {
-            AppError::InternalServerError(format!(
-                "Database error: {}", e
-            ))
-        })?;
+    // Return response, a user without activities gets an empty page rather than an error
+    let mut response = HttpResponse::Ok();
+    response.insert_header((TOTAL_COUNT_HEADER, page.meta.total.to_string()));
+    response.insert_header(header::LastModified(last_modified));
+    if query.cursor.is_none() {
+        response.insert_header((header::LINK, pagination_links(&req, limit, offset, page.meta.total)));
+    }
+    if query.compact == Some(true) {
+        let compact = CompactActivityListResponse::try_from(page)
+            .map_err(|_| AppError::InternalServerError("Failed to encode activities".to_string()))?;
+        return Ok(response.json(compact));
+    }
+    // Existing clients expect a bare array, with the total in X-Total-Count; cursor pages need
+    // meta.nextCursor
+    if query.envelope == Some(true) || query.cursor.is_some() {
+        return Ok(response.json(page));
+    }
+    Ok(response.json(page.data))
+}
 
-    // Return response
-    Ok(HttpResponse::Ok().json(activities))
+// RFC 8288 Link header with first/prev/next/last relations for limit/offset paging. Other query
+// parameters are carried over unchanged.
+fn pagination_links(req: &HttpRequest, limit: i64, offset: i64, total: i64) -> String {
+    let kept: Vec<&str> = req.query_string()
+        .split('&')
+        .filter(|pair| !pair.is_empty() && !pair.starts_with("limit=") && !pair.starts_with("offset="))
+        .collect();
+    let link = |offset: i64, rel: &str| {
+        let mut params = kept.clone();
+        let paging = format!("limit={}&offset={}", limit, offset);
+        params.push(&paging);
+        format!("<{}?{}>; rel=\"{}\"", req.path(), params.join("&"), rel)
+    };
+
+    let mut links = vec![link(0, "first")];
+    if offset > 0 {
+        links.push(link((offset - limit).max(0), "prev"));
+    }
+    if offset + limit < total {
+        links.push(link(offset + limit, "next"));
+    }
+    // Start of the page holding the last row, the first page when there are none
+    links.push(link((total - 1).max(0) / limit * limit, "last"));
+    links.join(", ")
 }
 
-// PATCH /v1/activity/:activityId
-pub async fn update_activity(
+// GET /v1/user/activities/recent
+pub async fn get_recent_activities(
     req: HttpRequest,
     pool: web::Data<sqlx::PgPool>,
-    activity_id: web::Path<Uuid>,
-    payload: web::Json<ActivityRequest>,
+    query: web::Query<RecentActivitiesQuery>,
 ) -> Result<HttpResponse, AppError> {
-    // Validate payload
-    payload.validate().map_err(|err| AppError::BadRequest(err.to_string()))?;
-
-    let extensions = req.extensions();
-    let claims = extensions.get::<Claims>().unwrap();
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
 
     // Fetch user from database
     let user = sqlx::query_as!(
         GetUserId,
         "SELECT user_id FROM users WHERE email = $1",
-        claims.sub
+        email
     )
     .fetch_optional(&**pool)
     .await
     .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
     .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
 
-    // Fetch activity from database
-    let activity = sqlx::query_as!(
-        GetActivityCreatedAt,
-        "SELECT created_at FROM activities WHERE activity_id = $1 AND user_id = $2",
-        *activity_id,
-        user.user_id
+    let limit = match query.limit {
+        Some(limit) if limit > 0 => limit.min(MAX_RECENT_LIMIT),
+        _ => DEFAULT_RECENT_LIMIT,
+    };
+    let page = fetch_activity_page(&pool, user.user_id, &GetActivitiesQuery::default(), PageOrder::NewestFirst, limit, 0).await?;
+
+    // Return response
+    Ok(HttpResponse::Ok().json(page))
+}
+
+pub fn export_buffer_rows() -> usize {
+    env::var("EXPORT_BUFFER_ROWS")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(64)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ExportFormat {
+    Json,
+    Ics,
+}
+
+impl ExportFormat {
+    fn parse(format: Option<&str>) -> Result<Self, AppError> {
+        match format {
+            None | Some("json") => Ok(ExportFormat::Json),
+            Some("ics") => Ok(ExportFormat::Ics),
+            Some(_) => Err(AppError::BadRequest("Format must be json or ics".to_string())),
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            ExportFormat::Ics => "text/calendar; charset=utf-8",
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "[",
+            ExportFormat::Ics => "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//FitByte//Activities//EN\r\nCALSCALE:GREGORIAN\r\nX-WR-CALNAME:FitByte activities\r\n",
+        }
+    }
+
+    fn footer(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "]",
+            ExportFormat::Ics => "END:VCALENDAR\r\n",
+        }
+    }
+
+    // One serialized activity, including the JSON array separator when it is not the first
+    fn entry(self, activity: Activity, first: bool) -> Result<Vec<u8>, String> {
+        match self {
+            ExportFormat::Json => {
+                let mut bytes = if first { Vec::new() } else { b",".to_vec() };
+                serde_json::to_writer(&mut bytes, &ActivityResponse::from(activity)).map_err(|err| err.to_string())?;
+                Ok(bytes)
+            }
+            ExportFormat::Ics => Ok(ics_event(&activity).into_bytes()),
+        }
+    }
+}
+
+// RFC 5545 TEXT escaping
+fn ics_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+// A content line folded at 75 octets, continuation lines starting with a space
+fn ics_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 4);
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+// The activity id makes the UID stable, so subscribed calendars update events instead of duplicating them
+fn ics_event(activity: &Activity) -> String {
+    let label = find_activity_type(&activity.activity_type)
+        .map(|info| info.label.as_str())
+        .unwrap_or(&activity.activity_type);
+    [
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}@fitbyte", activity.activity_id),
+        format!("DTSTAMP:{}", activity.updated_at.format("%Y%m%dT%H%M%SZ")),
+        format!("DTSTART:{}", activity.done_at.format("%Y%m%dT%H%M%SZ")),
+        format!("DURATION:PT{}M", activity.duration_in_minutes),
+        format!("SUMMARY:{}", ics_escape(label)),
+        format!("DESCRIPTION:{}", ics_escape(&format!("{} kcal", activity.calories_burned))),
+        "END:VEVENT".to_string(),
+    ]
+    .iter()
+    .map(|line| ics_line(line))
+    .collect()
+}
+
+// GET /v1/activity/export?format=json|ics
+// Streams every activity as one JSON array, or as an iCalendar feed with one event per activity.
+// Rows are serialized as they arrive from the database and at most EXPORT_BUFFER_ROWS of them
+// wait on a slow client, so memory does not grow with the account's history.
+pub async fn export_activities(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, AppError> {
+    let format = ExportFormat::parse(query.format.as_deref())?;
+
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    // Fetch user from database
+    let user = sqlx::query_as!(
+        GetUserId,
+        "SELECT user_id FROM users WHERE email = $1",
+        email
     )
     .fetch_optional(&**pool)
     .await
     .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
-    .ok_or_else(|| AppError::NotFound("Activity not found".to_string()))?;
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
 
-    // Parse done_at date
-    let done_at = DateTime::parse_from_rfc3339(&payload.done_at.as_ref().unwrap())
-        .map_err(|_| AppError::BadRequest("Invalid date format".to_string()))?
-        .with_timezone(&Utc);
+    let (sender, receiver) = mpsc::channel::<Result<web::Bytes, actix_web::Error>>(export_buffer_rows());
+    let pool = pool.get_ref().clone();
 
-    // Calculate calories burned
-    let calories_burned = calculate_calories_burned(
-        payload.activity_type.as_ref().unwrap(),
-        payload.duration_in_minutes.unwrap(),
-    )?;
+    actix_web::rt::spawn(async move {
+        let mut rows = sqlx::query_as::<_, Activity>(
+            "SELECT * FROM activities WHERE user_id = $1 ORDER BY done_at DESC, activity_id DESC"
+        )
+        .bind(user.user_id)
+        .fetch(&pool);
 
-    // Update activity in database
-    let now = Utc::now();
-    sqlx::query!(
-        "UPDATE activities SET activity_type = $1, done_at = $2, duration_in_minutes = $3, calories_burned = $4, updated_at = $5 WHERE activity_id = $6",
-        payload.activity_type.as_ref().unwrap(),
-        done_at,
-        payload.duration_in_minutes.unwrap(),
+        if sender.send(Ok(web::Bytes::from_static(format.header().as_bytes()))).await.is_err() {
+            return;
+        }
+
+        let mut first = true;
+        while let Some(row) = rows.next().await {
+            let item = match row.map_err(|err| err.to_string()).and_then(|activity| format.entry(activity, first)) {
+                Ok(bytes) => {
+                    first = false;
+                    Ok(web::Bytes::from(bytes))
+                }
+                Err(err) => {
+                    // Headers are already sent, so the only signal left is cutting the body short
+                    error!("Activity export failed: {}", err);
+                    Err(actix_web::error::ErrorInternalServerError("Export failed"))
+                }
+            };
+            let failed = item.is_err();
+            // A closed channel means the client went away; stop reading rows
+            if sender.send(item).await.is_err() || failed {
+                return;
+            }
+        }
+
+        let _ = sender.send(Ok(web::Bytes::from_static(format.footer().as_bytes()))).await;
+    });
+
+    let body = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|item| (item, receiver))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .streaming(body))
+}
+
+// Direction of the done_at ordering on offset pages (`ACTIVITY_SORT_DIRECTION`, asc or desc)
+pub fn default_sort_direction() -> &'static str {
+    parse_sort_direction(env::var("ACTIVITY_SORT_DIRECTION").ok().as_deref())
+}
+
+fn parse_sort_direction(value: Option<&str>) -> &'static str {
+    match value.map(str::to_ascii_lowercase).as_deref() {
+        Some("asc") => "ASC",
+        _ => "DESC",
+    }
+}
+
+// done_at ordering of an offset page. Only the list follows ACTIVITY_SORT_DIRECTION; "recent"
+// means newest first whatever the list default is.
+#[derive(Clone, Copy)]
+enum PageOrder {
+    Configured,
+    NewestFirst,
+}
+
+impl PageOrder {
+    // `configured` is the list default, normally default_sort_direction()
+    fn direction(self, configured: &'static str) -> &'static str {
+        match self {
+            PageOrder::Configured => configured,
+            PageOrder::NewestFirst => "DESC",
+        }
+    }
+}
+
+// Shared list logic: one filtered page of a user's activities plus the total count. Offset pages
+// follow done_at in the given order, cursor pages are always newest-created first.
+async fn fetch_activity_page(
+    pool: &sqlx::PgPool,
+    user_id: Uuid,
+    query: &GetActivitiesQuery,
+    order: PageOrder,
+    limit: i64,
+    offset: i64,
+) -> Result<ActivityListResponse, AppError> {
+    // Count matching rows for the pagination metadata
+    let mut count_query = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM activities WHERE user_id = ");
+    count_query.push_bind(user_id);
+    push_activity_filters(&mut count_query, query)?;
+
+    let total: i64 = timed("activities.count", count_query.build_query_scalar().fetch_one(pool))
+        .await
+        .map_err(|e| {
+            AppError::InternalServerError(format!(
+                "Database error: {}", e
+            ))
+        })?;
+
+    // Fetch activities for the user
+    let mut list_query = QueryBuilder::<Postgres>::new("SELECT * FROM activities WHERE user_id = ");
+    list_query.push_bind(user_id);
+    push_activity_filters(&mut list_query, query)?;
+
+    // Cursor mode pages by (created_at, activity_id) via idx_activities_user_created_id,
+    // otherwise plain limit/offset ordered by done_at
+    let cursor_mode = query.cursor.is_some();
+    if let Some(cursor) = query.cursor.as_deref().filter(|cursor| !cursor.is_empty()) {
+        let (created_at, activity_id) = parse_cursor(cursor)?;
+        list_query.push(" AND (created_at, activity_id) < (")
+            .push_bind(created_at)
+            .push(", ")
+            .push_bind(activity_id)
+            .push(")");
+    }
+
+    if cursor_mode {
+        list_query.push(" ORDER BY created_at DESC, activity_id DESC");
+        list_query.push(" LIMIT ").push_bind(limit);
+    } else {
+        // activity_id breaks done_at ties so rows never shift between pages
+        let direction = order.direction(default_sort_direction());
+        list_query.push(format_args!(" ORDER BY done_at {direction}, activity_id {direction}"));
+        list_query.push(" LIMIT ").push_bind(limit);
+        list_query.push(" OFFSET ").push_bind(offset);
+    }
+
+    let activities = timed("activities.list", list_query.build_query_as::<Activity>().fetch_all(pool))
+        .await
+        .map_err(|e| {
+            AppError::InternalServerError(format!(
+                "Database error: {}", e
+            ))
+        })?;
+
+    // A full page means there may be more rows after the last one
+    let next_cursor = match activities.last() {
+        Some(last) if cursor_mode && activities.len() as i64 == limit => {
+            Some(format!("{}_{}", last.created_at.timestamp_micros(), last.activity_id))
+        }
+        _ => None,
+    };
+
+    Ok(ActivityListResponse {
+        data: activities.into_iter().map(ActivityResponse::from).collect(),
+        meta: PaginationMeta { total, limit, offset, next_cursor },
+    })
+}
+
+// Appends the optional list filters, binding only the parameters that are present
+fn push_activity_filters(builder: &mut QueryBuilder<Postgres>, query: &GetActivitiesQuery) -> Result<(), AppError> {
+    if let Some(activity_type) = &query.activity_type {
+        builder.push(" AND activity_type = ").push_bind(activity_type.clone());
+    }
+
+    if let Some(preference_types) = &query.preference_types {
+        builder.push(" AND activity_type = ANY(").push_bind(preference_types.clone()).push(")");
+    }
+
+    if let Some((day_start, day_end)) = query.done_on_range {
+        builder.push(" AND done_at >= ").push_bind(day_start);
+        builder.push(" AND done_at < ").push_bind(day_end);
+    }
+
+    if let Some(done_at_from) = &query.done_at_from {
+        builder.push(" AND done_at >= ").push_bind(parse_filter_date(done_at_from)?);
+    }
+
+    if let Some(done_at_to) = &query.done_at_to {
+        builder.push(" AND done_at <= ").push_bind(parse_filter_date(done_at_to)?);
+    }
+
+    if let Some(calories_burned_min) = query.calories_burned_min {
+        builder.push(" AND calories_burned >= ").push_bind(calories_burned_min);
+    }
+
+    if let Some(calories_burned_max) = query.calories_burned_max {
+        builder.push(" AND calories_burned <= ").push_bind(calories_burned_max);
+    }
+
+    if let Some(tag) = &query.tag {
+        let tags = normalize_tags(tag.split(','))?;
+        if !tags.is_empty() {
+            // && is array overlap, @> containment; both are served by idx_activities_tags
+            let operator = match query.tag_match.as_deref() {
+                None | Some("any") => " && ",
+                Some("all") => " @> ",
+                Some(_) => return Err(AppError::BadRequest("Tag match must be either any or all".to_string())),
+            };
+            builder.push(" AND tags").push(operator).push_bind(tags);
+        }
+    }
+
+    Ok(())
+}
+
+// Cursors are "<created_at micros>_<activity_id>" of the last row on the previous page
+fn parse_cursor(cursor: &str) -> Result<(DateTime<Utc>, Uuid), AppError> {
+    let invalid = || AppError::BadRequest("Invalid cursor".to_string());
+    let (micros, activity_id) = cursor.split_once('_').ok_or_else(invalid)?;
+    let created_at = micros.parse::<i64>()
+        .ok()
+        .and_then(DateTime::from_timestamp_micros)
+        .ok_or_else(invalid)?;
+    let activity_id = Uuid::parse_str(activity_id).map_err(|_| invalid())?;
+    Ok((created_at, activity_id))
+}
+
+// When enabled, doneAt values without an offset or Z are rejected instead of read as UTC
+pub fn require_tz_in_done_at() -> bool {
+    env::var("REQUIRE_TZ_IN_DONE_AT").map(|v| v == "true").unwrap_or(false)
+}
+
+// Parses a submitted doneAt. RFC 3339 is taken as is; a naive local timestamp is read as UTC
+// unless REQUIRE_TZ_IN_DONE_AT is set.
+fn parse_done_at(value: &str) -> Result<DateTime<FixedOffset>, &'static str> {
+    parse_done_at_with(value, require_tz_in_done_at())
+}
+
+fn parse_done_at_with(value: &str, require_tz: bool) -> Result<DateTime<FixedOffset>, &'static str> {
+    if let Ok(done_at) = DateTime::parse_from_rfc3339(value) {
+        return Ok(done_at);
+    }
+
+    let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+        .map_err(|_| "Invalid date format")?;
+    if require_tz {
+        return Err("doneAt must include a timezone offset or Z");
+    }
+    Ok(naive.and_utc().fixed_offset())
+}
+
+fn parse_filter_date(value: &str) -> Result<DateTime<Utc>, AppError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|date| date.with_timezone(&Utc))
+        .map_err(|_| AppError::BadRequest("Invalid date format".to_string()))
+}
+
+// PATCH /v1/activity/:activityId
+pub async fn update_activity(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+    activity_id: web::Path<Uuid>,
+    payload: web::Json<ActivityRequest>,
+) -> Result<HttpResponse, AppError> {
+    // Validate payload
+    payload.validate().map_err(|err| AppError::BadRequest(err.to_string()))?;
+
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    // Fetch user from database
+    let user = sqlx::query_as!(
+        GetUserId,
+        "SELECT user_id FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    // Parse done_at date, keeping the submitted offset for doneAtLocal
+    let submitted_done_at = parse_done_at(payload.done_at.as_ref().unwrap())
+        .map_err(|msg| AppError::BadRequest(msg.to_string()))?;
+    let done_at = submitted_done_at.with_timezone(&Utc);
+    let done_at_offset = submitted_done_at.offset().local_minus_utc();
+
+    // Calculate calories burned, unless the client supplied its own value
+    let (calories_burned, calories_source) = resolve_calories(&payload)?;
+
+    // Tags are optional on update; leaving them out keeps the current ones
+    let tags = payload.tags
+        .as_ref()
+        .map(|tags| normalize_tags(tags.iter().map(String::as_str)))
+        .transpose()?;
+
+    let mut tx = pool.begin()
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    // Fetch activity from database, locking it so the lifetime counter delta stays correct
+    let activity = sqlx::query_as!(
+        GetActivityForUpdate,
+        "SELECT created_at, calories_burned FROM activities WHERE activity_id = $1 AND user_id = $2 FOR UPDATE",
+        *activity_id,
+        user.user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("Activity not found".to_string()))?;
+
+    // Update activity in database
+    // updated_at is set by the activities_set_updated_at trigger
+    let updated = sqlx::query!(
+        "UPDATE activities SET activity_type = $1, done_at = $2, done_at_offset = $7, duration_in_minutes = $3, calories_burned = $4, calories_source = $5, tags = COALESCE($8, tags) WHERE activity_id = $6 RETURNING updated_at, tags",
+        payload.activity_type.as_ref().unwrap(),
+        done_at,
+        payload.duration_in_minutes.unwrap(),
         calories_burned,
-        now,
-        *activity_id
+        calories_source,
+        *activity_id,
+        done_at_offset,
+        tags.as_deref()
     )
-    .execute(&**pool)
+    .fetch_one(&mut *tx)
     .await
     .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
 
+    adjust_lifetime_calories(&mut tx, user.user_id, (calories_burned - activity.calories_burned) as i64).await?;
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
     // Return response
     Ok(HttpResponse::Ok().json(ActivityResponse {
         activity_id: *activity_id,
         activity_type: payload.activity_type.clone().unwrap(),
-        done_at: payload.done_at.clone().unwrap(),
+        category: activity_category(payload.activity_type.as_ref().unwrap()),
+        met: activity_met(payload.activity_type.as_ref().unwrap()),
+        done_at: done_at.to_rfc3339(),
+        done_at_local: Some(submitted_done_at.to_rfc3339()),
         duration_in_minutes: payload.duration_in_minutes.unwrap(),
         calories_burned,
+        calories_source: calories_source.to_string(),
         created_at: activity.created_at.to_rfc3339(),
-        updated_at: now.to_rfc3339(),
+        updated_at: updated.updated_at.to_rfc3339(),
+        tags: updated.tags,
     }))
 }
 
@@ -282,30 +1311,1262 @@ pub async fn delete_activity(
     pool: web::Data<sqlx::PgPool>,
     activity_id: web::Path<Uuid>,
 ) -> Result<HttpResponse, AppError> {
-    let extensions = req.extensions();
-    let claims = extensions.get::<Claims>().unwrap();
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
 
     // Fetch user from database
     let user = sqlx::query_as!(
         GetUserId,
         "SELECT user_id FROM users WHERE email = $1",
-        claims.sub
+        email
     )
     .fetch_optional(&**pool)
     .await
     .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
     .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
 
-    // Delete activity from database
-    sqlx::query!(
-        "DELETE FROM activities WHERE activity_id = $1 AND user_id = $2",
+    // Delete activity from database, keeping the lifetime counter in the same transaction
+    let mut tx = pool.begin()
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    let deleted_calories = sqlx::query_scalar!(
+        "DELETE FROM activities WHERE activity_id = $1 AND user_id = $2 RETURNING calories_burned",
         *activity_id,
         user.user_id
     )
-    .execute(&**pool)
+    .fetch_optional(&mut *tx)
     .await
     .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
 
+    if let Some(calories) = deleted_calories {
+        adjust_lifetime_calories(&mut tx, user.user_id, -(calories as i64)).await?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
     // Return response
     Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Activity deleted successfully" })))
-}
\ No newline at end of file
+}
+
+// GET /v1/activity/:activityId/calorie-breakdown
+pub async fn get_calorie_breakdown(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+    activity_id: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let email = req.extensions().get::<Claims>().map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    // Fetch user from database
+    let user = sqlx::query_as!(
+        GetUserId,
+        "SELECT user_id FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let activity = sqlx::query_as!(
+        Activity,
+        "SELECT * FROM activities WHERE activity_id = $1 AND user_id = $2",
+        *activity_id,
+        user.user_id
+    )
+    .fetch_optional(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("Activity not found".to_string()))?;
+
+    // Return response
+    Ok(HttpResponse::Ok().json(calorie_breakdown(activity)))
+}
+
+// Same formula as calculate_calories_burned; types since removed from the registry have no inputs,
+// and a product that would overflow has no computed value
+fn calorie_breakdown(activity: Activity) -> CalorieBreakdownResponse {
+    let info = find_activity_type(&activity.activity_type);
+
+    CalorieBreakdownResponse {
+        activity_id: activity.activity_id,
+        duration_in_minutes: activity.duration_in_minutes,
+        calories_source: activity.calories_source,
+        formula: "caloriesPerMinute * durationInMinutes",
+        calories_per_minute: info.map(|info| info.calories_per_minute),
+        met: info.map(|info| info.met()),
+        rounding: "none, integer multiplier",
+        computed_calories: info.and_then(|info| info.calories_per_minute.checked_mul(activity.duration_in_minutes)),
+        calories_burned: activity.calories_burned,
+        activity_type: activity.activity_type,
+    }
+}
+
+// DELETE /v1/activity?confirm=true
+pub async fn delete_all_activities(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+    query: web::Query<DeleteAllQuery>,
+) -> Result<HttpResponse, AppError> {
+    // Clearing the whole history must be asked for explicitly
+    if query.confirm != Some(true) {
+        return Err(AppError::BadRequest("Pass confirm=true to delete all activities".to_string()));
+    }
+
+    let email = req.extensions().get::<Claims>().map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    // Fetch user from database
+    let user = sqlx::query_as!(
+        GetUserId,
+        "SELECT user_id FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    // Delete every activity in one statement, keeping the lifetime counter in the same transaction
+    let mut tx = pool.begin()
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    let deleted = sqlx::query!(
+        r#"WITH deleted AS (
+            DELETE FROM activities WHERE user_id = $1 RETURNING calories_burned
+        )
+        SELECT COUNT(*) AS "count!", COALESCE(SUM(calories_burned), 0)::BIGINT AS "calories!" FROM deleted"#,
+        user.user_id
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    if deleted.calories != 0 {
+        adjust_lifetime_calories(&mut tx, user.user_id, -deleted.calories).await?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "deleted": deleted.count })))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecomputeQuery {
+    activity_type: Option<String>,
+    user_id: Option<Uuid>,
+}
+
+// Rows locked and rewritten per transaction by the recompute endpoint
+pub fn recompute_batch_size() -> i64 {
+    env::var("RECOMPUTE_BATCH_SIZE")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(500)
+}
+
+// New calories for a formula-derived row under the current registry entry for its type, or None
+// when they would not change. Goes through the same rules as a new activity, so a type that is no
+// longer configured, a result over the type's cap or a rejected zero is an error.
+fn recompute_row(
+    info: Option<&ActivityTypeInfo>,
+    duration: i32,
+    stored: i32,
+    reject_zero: bool,
+) -> Result<Option<i32>, AppError> {
+    let info = info.ok_or_else(|| AppError::BadRequest("Invalid activity type".to_string()))?;
+    let (recomputed, _) = resolve_calories_with(info, duration, None, reject_zero)?;
+    Ok(Some(recomputed).filter(|recomputed| *recomputed != stored))
+}
+
+// POST /v1/activity/recompute
+// Re-derives calories_burned from the current activity type registry. Rows whose calories were
+// supplied by the client are left alone. Rows the current config rejects keep their value and
+// are counted as skipped.
+pub async fn recompute_activities(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+    query: web::Query<RecomputeQuery>,
+) -> Result<HttpResponse, AppError> {
+    require_admin(&req, &pool).await?;
+
+    if let Some(activity_type) = &query.activity_type {
+        find_activity_type(activity_type)
+            .ok_or_else(|| AppError::BadRequest("Invalid activity type".to_string()))?;
+    }
+
+    let batch_size = recompute_batch_size();
+    let reject_zero = reject_zero_calories();
+    let mut cursor = Uuid::nil();
+    let mut updated: i64 = 0;
+    let mut skipped: i64 = 0;
+
+    loop {
+        let mut tx = pool.begin()
+            .await
+            .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+        // Keyset pagination on activity_id, so each batch is one short transaction
+        let rows = sqlx::query!(
+            "SELECT activity_id, user_id, activity_type, duration_in_minutes, calories_burned FROM activities
+            WHERE calories_source = $1 AND activity_id > $2
+            AND ($3::text IS NULL OR activity_type = $3)
+            AND ($4::uuid IS NULL OR user_id = $4)
+            ORDER BY activity_id LIMIT $5 FOR UPDATE",
+            CALORIES_SOURCE_COMPUTED,
+            cursor,
+            query.activity_type,
+            query.user_id,
+            batch_size
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+        let Some(last) = rows.last() else {
+            break;
+        };
+        cursor = last.activity_id;
+
+        let mut activity_ids = Vec::new();
+        let mut calories = Vec::new();
+        let mut deltas: HashMap<Uuid, i64> = HashMap::new();
+        for row in &rows {
+            let info = find_activity_type(&row.activity_type);
+            let recomputed = match recompute_row(info, row.duration_in_minutes, row.calories_burned, reject_zero) {
+                Ok(Some(recomputed)) => recomputed,
+                Ok(None) => continue,
+                Err(err) => {
+                    warn!("Recompute skipped activity {}: {}", row.activity_id, err);
+                    skipped += 1;
+                    continue;
+                }
+            };
+            activity_ids.push(row.activity_id);
+            calories.push(recomputed);
+            *deltas.entry(row.user_id).or_insert(0) += (recomputed - row.calories_burned) as i64;
+        }
+
+        if !activity_ids.is_empty() {
+            sqlx::query!(
+                "UPDATE activities SET calories_burned = changed.calories_burned
+                FROM UNNEST($1::uuid[], $2::int[]) AS changed(activity_id, calories_burned)
+                WHERE activities.activity_id = changed.activity_id",
+                &activity_ids,
+                &calories
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+            for (user_id, delta) in deltas {
+                adjust_lifetime_calories(&mut tx, user_id, delta).await?;
+            }
+        }
+
+        tx.commit()
+            .await
+            .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+        updated += activity_ids.len() as i64;
+        if (rows.len() as i64) < batch_size {
+            break;
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "updated": updated, "skipped": skipped })))
+}
+
+// Parses and validates a single CSV row into (activity_type, done_at, duration, calories)
+fn parse_import_row(record: &csv::StringRecord) -> Result<(String, DateTime<FixedOffset>, i32, i32), String> {
+    if record.len() != 3 {
+        return Err(format!("Expected 3 columns, found {}", record.len()));
+    }
+
+    let activity_type = record[0].to_string();
+    if activity_type.is_empty() {
+        return Err("Activity type cannot be empty".to_string());
+    }
+
+    let done_at = parse_done_at(&record[1]).map_err(str::to_string)?;
+
+    let duration = record[2]
+        .parse::<i32>()
+        .map_err(|_| "Duration must be a whole number of minutes".to_string())?;
+    if duration < 1 {
+        return Err("Duration must be at least 1 minute".to_string());
+    }
+
+    // Same rules as a JSON activity without calories, REJECT_ZERO_CALORIES included
+    let (calories_burned, _) = resolve_calories_for(&activity_type, duration, None).map_err(|err| match err {
+        AppError::BadRequest(msg) => msg,
+        other => other.to_string(),
+    })?;
+
+    Ok((activity_type, done_at, duration, calories_burned))
+}
+
+// (activity_type, done_at, duration, calories, calories_source, tags) ready to insert
+type PreparedActivity = (String, DateTime<FixedOffset>, i32, i32, &'static str, Vec<String>);
+
+// Validates one bulk item for insertion, with the same rules as create_activity
+fn prepare_bulk_item(item: &ActivityRequest, weight: Option<f64>, require_weight: bool) -> Result<PreparedActivity, String> {
+    item.validate().map_err(|err| err.to_string())?;
+    check_weight_for_activity(weight, require_weight).map_err(|err| match err {
+        AppError::BadRequest(msg) => msg,
+        other => other.to_string(),
+    })?;
+
+    let done_at = parse_done_at(item.done_at.as_ref().unwrap()).map_err(str::to_string)?;
+    let (calories_burned, calories_source) = resolve_calories(item).map_err(|err| match err {
+        AppError::BadRequest(msg) => msg,
+        other => other.to_string(),
+    })?;
+    let tags = normalize_tags(item.tags.iter().flatten().map(String::as_str)).map_err(|err| match err {
+        AppError::BadRequest(msg) => msg,
+        other => other.to_string(),
+    })?;
+
+    Ok((
+        item.activity_type.clone().unwrap(),
+        done_at,
+        item.duration_in_minutes.unwrap(),
+        calories_burned,
+        calories_source,
+        tags,
+    ))
+}
+
+// Inserts a bulk batch in one transaction. Atomic mode fails on the first rejected item; partial
+// mode inserts each item under its own savepoint and reports the rejected ones per index.
+async fn insert_bulk_items(
+    pool: &sqlx::PgPool,
+    user: &GetUserWeight,
+    items: &[ActivityRequest],
+    partial: bool,
+    rules: CreationRules,
+) -> Result<Vec<BulkItemResult>, AppError> {
+    let prepared: Vec<_> = items.iter()
+        .map(|item| prepare_bulk_item(item, user.weight, rules.require_weight))
+        .collect();
+
+    // Atomic mode rejects the whole batch before touching the database
+    if !partial {
+        if let Some((index, Err(error))) = prepared.iter().enumerate().find(|(_, item)| item.is_err()) {
+            return Err(AppError::BadRequest(format!("Item {}: {}", index, error)));
+        }
+    }
+
+    let now = Utc::now();
+    let mut tx = pool.begin()
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    let mut results = Vec::with_capacity(prepared.len());
+    let mut total_calories: i64 = 0;
+
+    for (index, item) in prepared.into_iter().enumerate() {
+        let (activity_type, done_at, duration, calories_burned, calories_source, tags) = match item {
+            Ok(item) => item,
+            Err(error) => {
+                results.push(BulkItemResult { index, activity_id: None, error: Some(error) });
+                continue;
+            }
+        };
+
+        // Nested begin() is a savepoint, rolled back on drop if the insert fails
+        let mut savepoint = tx.begin()
+            .await
+            .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+        // Earlier items of the batch are already visible here, so they count as taking their day
+        if rules.one_per_day {
+            match ensure_day_is_free(&mut savepoint, user.user_id, done_at.with_timezone(&Utc)).await {
+                Ok(_) => {}
+                Err(AppError::Conflict(error)) if partial => {
+                    savepoint.rollback()
+                        .await
+                        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+                    results.push(BulkItemResult { index, activity_id: None, error: Some(error) });
+                    continue;
+                }
+                Err(AppError::Conflict(error)) => {
+                    return Err(AppError::Conflict(format!("Item {}: {}", index, error)));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let activity_id = Uuid::now_v7();
+        let inserted = sqlx::query!(
+            "INSERT INTO activities (activity_id, user_id, activity_type, done_at, done_at_offset, duration_in_minutes, calories_burned, calories_source, created_at, updated_at, tags) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+            activity_id,
+            user.user_id,
+            activity_type,
+            done_at.with_timezone(&Utc),
+            done_at.offset().local_minus_utc(),
+            duration,
+            calories_burned,
+            calories_source,
+            now,
+            now,
+            &tags
+        )
+        .execute(&mut *savepoint)
+        .await;
+
+        match inserted {
+            Ok(_) => {
+                savepoint.commit()
+                    .await
+                    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+                total_calories += calories_burned as i64;
+                results.push(BulkItemResult { index, activity_id: Some(activity_id), error: None });
+            }
+            Err(_) if partial => {
+                savepoint.rollback()
+                    .await
+                    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+                results.push(BulkItemResult { index, activity_id: None, error: Some("Database error".to_string()) });
+            }
+            Err(_) => return Err(AppError::InternalServerError("Database error".to_string())),
+        }
+    }
+
+    if total_calories != 0 {
+        adjust_lifetime_calories(&mut tx, user.user_id, total_calories).await?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    Ok(results)
+}
+
+// POST /v1/activity/bulk
+//
+// All-or-nothing by default. With `?mode=partial` each item is inserted under its own savepoint
+// so valid items commit and the rest are reported per index.
+pub async fn bulk_create_activities(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+    query: web::Query<BulkCreateQuery>,
+    payload: web::Json<Vec<ActivityRequest>>,
+) -> Result<HttpResponse, AppError> {
+    let partial = match query.mode.as_deref() {
+        None | Some("atomic") => false,
+        Some("partial") => true,
+        Some(_) => return Err(AppError::BadRequest("Mode must be either atomic or partial".to_string())),
+    };
+
+    if payload.is_empty() {
+        return Err(AppError::BadRequest("At least one activity is required".to_string()));
+    }
+    validate_batch_size(payload.len())?;
+
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    // Fetch user from database
+    let user = counted(sqlx::query_as!(
+        GetUserWeight,
+        "SELECT user_id, weight FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(&**pool))
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let reservation = reserve_activity_creations(user.user_id, payload.len())?;
+    let results = insert_bulk_items(&pool, &user, &payload, partial, CreationRules::from_env()).await?;
+
+    let failed = results.iter().filter(|result| result.error.is_some()).count();
+    let created = results.len() - failed;
+    let response = BulkCreateResponse {
+        created,
+        failed,
+        results,
+    };
+
+    // Return response
+    let mut builder = if failed == 0 { HttpResponse::Created() } else { HttpResponse::Ok() };
+    commit_activity_creations(&mut builder, reservation, created);
+    Ok(builder.json(response))
+}
+
+// POST /v1/activity/import
+pub async fn import_activities(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+    query: web::Query<ImportQuery>,
+    payload: web::Payload,
+) -> Result<HttpResponse, AppError> {
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    let strict = query.strict.unwrap_or(false);
+    let unknown_type_policy = UnknownTypePolicy::parse(query.unknown_type_policy.as_deref())?;
+
+    // Collect the CSV body from the "file" field
+    let mut multipart = Multipart::new(req.headers(), payload);
+    let mut csv_data = Vec::new();
+
+    while let Some(item) = multipart.next().await {
+        let mut field = item.map_err(|_| AppError::BadRequest("Invalid multipart field".to_string()))?;
+
+        if field.name() != "file" {
+            return Err(AppError::BadRequest("Invalid field name: expected 'file'".to_string()));
+        }
+
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|_| AppError::BadRequest("Failed to read chunk".to_string()))?;
+            if csv_data.len() + chunk.len() > MAX_IMPORT_BYTES {
+                return Err(AppError::BadRequest("CSV file exceeds 1MiB limit".to_string()));
+            }
+            csv_data.extend_from_slice(&chunk);
+        }
+    }
+
+    if csv_data.is_empty() {
+        return Err(AppError::BadRequest("File part is missing".to_string()));
+    }
+
+    // Fetch user from database
+    let user = counted(sqlx::query_as!(
+        GetUserWeight,
+        "SELECT user_id, weight FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(&**pool))
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let parsed = parse_import_csv(&csv_data, unknown_type_policy)?;
+    let (response, reservation) = insert_import_rows(&pool, &user, parsed, strict, CreationRules::from_env()).await?;
+
+    // Return response
+    let mut builder = HttpResponse::Ok();
+    commit_activity_creations(&mut builder, reservation, response.imported);
+    Ok(builder.json(response))
+}
+
+// One CSV row that parsed, with its 1-based line number
+struct ImportRow {
+    row: usize,
+    activity_type: String,
+    done_at: DateTime<FixedOffset>,
+    duration: i32,
+    calories: i32,
+    mapping: Option<ImportTypeMapping>,
+}
+
+// A CSV body split into parsed rows and the rows left out, before anything is written
+struct ParsedImport {
+    rows: Vec<ImportRow>,
+    errors: Vec<ImportRowError>,
+    unknown_types: Vec<ImportTypeSkip>,
+}
+
+// Parses every row of a CSV body, applying the unknown type policy. Only a body over the batch
+// size fails as a whole; bad rows are reported by line.
+fn parse_import_csv(csv_data: &[u8], unknown_type_policy: UnknownTypePolicy) -> Result<ParsedImport, AppError> {
+    let reader = || csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(csv_data);
+
+    // Count data rows before parsing any of them; the header row is optional
+    let mut row_count = 0;
+    for (index, record) in reader().records().enumerate() {
+        let is_header = index == 0 && record.as_ref()
+            .is_ok_and(|record| record.get(0).is_some_and(|col| col.eq_ignore_ascii_case("activityType")));
+        if !is_header {
+            row_count += 1;
+        }
+    }
+    validate_batch_size(row_count)?;
+
+    let mut parsed = ParsedImport { rows: Vec::new(), errors: Vec::new(), unknown_types: Vec::new() };
+
+    for (index, record) in reader().records().enumerate() {
+        let row = index + 1;
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                parsed.errors.push(ImportRowError { row, error: err.to_string() });
+                continue;
+            }
+        };
+
+        // The header row is optional
+        if row == 1 && record.get(0).is_some_and(|col| col.eq_ignore_ascii_case("activityType")) {
+            continue;
+        }
+
+        // Unknown types are handled per policy before the row is validated
+        let unknown_type = record.get(0)
+            .filter(|activity_type| !activity_type.is_empty() && find_activity_type(activity_type).is_none())
+            .map(str::to_string);
+        let (record, mapping) = match (unknown_type, unknown_type_policy) {
+            (Some(activity_type), UnknownTypePolicy::Skip) => {
+                parsed.unknown_types.push(ImportTypeSkip { row, activity_type });
+                continue;
+            }
+            (Some(activity_type), UnknownTypePolicy::Map) => match resolve_activity_alias(&activity_type) {
+                Some(target) => {
+                    let mut fields: Vec<&str> = record.iter().collect();
+                    fields[0] = target;
+                    (csv::StringRecord::from(fields), Some(ImportTypeMapping { row, from: activity_type, to: target }))
+                }
+                None => (record, None),
+            },
+            _ => (record, None),
+        };
+
+        match parse_import_row(&record) {
+            Ok((activity_type, done_at, duration, calories)) => {
+                parsed.rows.push(ImportRow { row, activity_type, done_at, duration, calories, mapping });
+            }
+            Err(error) => parsed.errors.push(ImportRowError { row, error }),
+        }
+    }
+
+    Ok(parsed)
+}
+
+// Applies the creation rules to the parsed rows and inserts the ones that pass in one
+// transaction. In strict mode the first rejected row, by line, fails the whole import.
+async fn insert_import_rows(
+    pool: &sqlx::PgPool,
+    user: &GetUserWeight,
+    parsed: ParsedImport,
+    strict: bool,
+    rules: CreationRules,
+) -> Result<(ImportResponse, Option<Reservation>), AppError> {
+    let ParsedImport { rows, mut errors, unknown_types } = parsed;
+
+    // Applies to the user rather than a row, so the import is rejected like create_activity
+    check_weight_for_activity(user.weight, rules.require_weight)?;
+
+    let mut tx = pool.begin()
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    // Rows are checked against stored activities and against earlier rows of the file
+    let rows = if rules.one_per_day {
+        let mut claimed = HashSet::new();
+        let mut free = Vec::with_capacity(rows.len());
+        for row in rows {
+            match ensure_day_is_free(&mut tx, user.user_id, row.done_at.with_timezone(&Utc)).await {
+                Ok(day) if claimed.insert(day) => free.push(row),
+                Ok(day) => errors.push(ImportRowError { row: row.row, error: format!("An activity is already logged on {}", day) }),
+                Err(AppError::Conflict(error)) => errors.push(ImportRowError { row: row.row, error }),
+                Err(err) => return Err(err),
+            }
+        }
+        errors.sort_by_key(|error| error.row);
+        free
+    } else {
+        rows
+    };
+
+    if strict {
+        if let Some(first) = errors.first() {
+            return Err(AppError::BadRequest(format!("Row {}: {}", first.row, first.error)));
+        }
+    }
+
+    let reservation = reserve_activity_creations(user.user_id, rows.len())?;
+
+    // Bulk insert all valid rows in one transaction
+    let now = Utc::now();
+    let mut activity_ids = Vec::with_capacity(rows.len());
+    let mut activity_types = Vec::with_capacity(rows.len());
+    let mut done_ats = Vec::with_capacity(rows.len());
+    let mut done_at_offsets = Vec::with_capacity(rows.len());
+    let mut durations = Vec::with_capacity(rows.len());
+    let mut calories = Vec::with_capacity(rows.len());
+    let mut mapped_types = Vec::new();
+    for row in rows {
+        activity_ids.push(Uuid::now_v7());
+        activity_types.push(row.activity_type);
+        done_ats.push(row.done_at.with_timezone(&Utc));
+        done_at_offsets.push(row.done_at.offset().local_minus_utc());
+        durations.push(row.duration);
+        calories.push(row.calories);
+        mapped_types.extend(row.mapping);
+    }
+
+    if !activity_ids.is_empty() {
+        sqlx::query!(
+            "INSERT INTO activities (activity_id, user_id, activity_type, done_at, done_at_offset, duration_in_minutes, calories_burned, created_at, updated_at)
+            SELECT activity_id, $2, activity_type, done_at, done_at_offset, duration_in_minutes, calories_burned, $6, $6
+            FROM UNNEST($1::uuid[], $3::varchar[], $4::timestamptz[], $8::int[], $5::int[], $7::int[])
+                AS t(activity_id, activity_type, done_at, done_at_offset, duration_in_minutes, calories_burned)",
+            &activity_ids,
+            user.user_id,
+            &activity_types,
+            &done_ats,
+            &durations,
+            now,
+            &calories,
+            &done_at_offsets
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+        adjust_lifetime_calories(&mut tx, user.user_id, calories.iter().map(|c| *c as i64).sum()).await?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    let response = ImportResponse {
+        imported: activity_ids.len(),
+        skipped: errors.len() + unknown_types.len(),
+        errors,
+        unknown_types,
+        mapped_types,
+    };
+    Ok((response, reservation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{authenticated, create_user, delete_user, response_json, test_pool};
+
+    #[test]
+    fn recent_stays_newest_first_when_list_sorts_ascending() {
+        assert_eq!(PageOrder::Configured.direction("ASC"), "ASC");
+        assert_eq!(PageOrder::NewestFirst.direction("ASC"), "DESC");
+
+        assert_eq!(PageOrder::Configured.direction("DESC"), "DESC");
+        assert_eq!(PageOrder::NewestFirst.direction("DESC"), "DESC");
+    }
+
+    #[test]
+    fn sort_direction_defaults_to_descending() {
+        assert_eq!(parse_sort_direction(Some("asc")), "ASC");
+        assert_eq!(parse_sort_direction(Some("ASC")), "ASC");
+        assert_eq!(parse_sort_direction(Some("desc")), "DESC");
+        assert_eq!(parse_sort_direction(Some("sideways")), "DESC");
+        assert_eq!(parse_sort_direction(None), "DESC");
+    }
+
+    fn import_row(duration: &str) -> Result<(String, DateTime<FixedOffset>, i32, i32), String> {
+        parse_import_row(&csv::StringRecord::from(vec!["Running", "2024-01-01T07:00:00Z", duration]))
+    }
+
+    #[test]
+    fn import_row_computes_calories_like_json() {
+        let (_, _, duration, calories) = import_row("30").unwrap();
+        assert_eq!((duration, calories), (30, 300));
+    }
+
+    #[test]
+    fn import_row_rejects_durations_json_would_reject() {
+        assert_eq!(import_row("0").unwrap_err(), "Duration must be at least 1 minute");
+        assert_eq!(import_row("1.5").unwrap_err(), "Duration must be a whole number of minutes");
+        assert_eq!(import_row("300000000").unwrap_err(), "Duration is too long");
+    }
+
+    fn sample_activity() -> Activity {
+        let done_at = DateTime::parse_from_rfc3339("2024-03-01T07:30:00+07:00").unwrap().with_timezone(&Utc);
+        Activity {
+            activity_id: Uuid::nil(),
+            user_id: Uuid::nil(),
+            activity_type: "JumpRope".to_string(),
+            done_at,
+            done_at_offset: Some(7 * 3600),
+            duration_in_minutes: 45,
+            calories_burned: 450,
+            calories_source: CALORIES_SOURCE_COMPUTED.to_string(),
+            created_at: done_at,
+            updated_at: done_at,
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn ics_export_wraps_one_event_per_activity() {
+        let format = ExportFormat::Ics;
+        let feed = [
+            format.header().to_string(),
+            String::from_utf8(format.entry(sample_activity(), true).unwrap()).unwrap(),
+            format.footer().to_string(),
+        ]
+        .concat();
+        let lines: Vec<&str> = feed.split_terminator("\r\n").collect();
+
+        assert_eq!(lines.first(), Some(&"BEGIN:VCALENDAR"));
+        assert_eq!(lines.last(), Some(&"END:VCALENDAR"));
+        assert_eq!(lines.iter().filter(|line| **line == "BEGIN:VEVENT").count(), 1);
+        assert!(lines.contains(&"UID:00000000-0000-0000-0000-000000000000@fitbyte"));
+        assert!(lines.contains(&"DTSTART:20240301T003000Z"));
+        assert!(lines.contains(&"DURATION:PT45M"));
+        assert!(lines.contains(&"SUMMARY:Jump Rope"));
+        assert!(lines.contains(&"DESCRIPTION:450 kcal"));
+    }
+
+    #[test]
+    fn ics_text_is_escaped_and_folded() {
+        assert_eq!(ics_escape("a;b,c\\d\ne"), "a\\;b\\,c\\\\d\\ne");
+
+        let folded = ics_line(&"x".repeat(80));
+        assert_eq!(folded, format!("{}\r\n {}\r\n", "x".repeat(75), "x".repeat(5)));
+    }
+
+    #[test]
+    fn missing_weight_is_rejected_only_when_required() {
+        let err = check_weight_for_activity(None, true).unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(msg) if msg == "Set your weight in your profile first"));
+        assert!(check_weight_for_activity(Some(70.0), true).is_ok());
+        assert!(check_weight_for_activity(None, false).is_ok());
+    }
+
+    fn bulk_item(done_at: &str, duration: i32) -> ActivityRequest {
+        serde_json::from_value(serde_json::json!({
+            "activityType": "Running",
+            "doneAt": done_at,
+            "durationInMinutes": duration,
+        }))
+        .unwrap()
+    }
+
+    async fn activity_count(pool: &sqlx::PgPool, user_id: Uuid) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM activities WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    const ONE_PER_DAY: CreationRules = CreationRules { one_per_day: true, require_weight: false };
+    const REQUIRE_WEIGHT: CreationRules = CreationRules { one_per_day: false, require_weight: true };
+
+    #[tokio::test]
+    async fn partial_bulk_reports_taken_days_per_item() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, _) = create_user(&pool).await;
+        let user = GetUserWeight { user_id, weight: None };
+        let items = [
+            bulk_item("2024-03-01T08:00:00Z", 30),
+            bulk_item("2024-03-01T18:00:00Z", 30),
+            bulk_item("2024-03-02T08:00:00Z", 30),
+        ];
+
+        let results = insert_bulk_items(&pool, &user, &items, true, ONE_PER_DAY).await.unwrap();
+        assert!(results[0].activity_id.is_some());
+        assert_eq!(results[1].error.as_deref(), Some("An activity is already logged on 2024-03-01"));
+        assert!(results[2].activity_id.is_some());
+        assert_eq!(activity_count(&pool, user_id).await, 2);
+
+        delete_user(&pool, user_id).await;
+    }
+
+    #[tokio::test]
+    async fn atomic_bulk_rejects_a_taken_day_whole() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, _) = create_user(&pool).await;
+        let user = GetUserWeight { user_id, weight: None };
+        let items = [bulk_item("2024-03-01T08:00:00Z", 30), bulk_item("2024-03-01T18:00:00Z", 30)];
+
+        let result = insert_bulk_items(&pool, &user, &items, false, ONE_PER_DAY).await;
+        assert!(matches!(result, Err(AppError::Conflict(msg)) if msg == "Item 1: An activity is already logged on 2024-03-01"));
+        assert_eq!(activity_count(&pool, user_id).await, 0);
+
+        // Without the rule both go in
+        let results = insert_bulk_items(&pool, &user, &items, false, CreationRules { one_per_day: false, require_weight: false }).await.unwrap();
+        assert!(results.iter().all(|result| result.error.is_none()));
+        assert_eq!(activity_count(&pool, user_id).await, 2);
+
+        delete_user(&pool, user_id).await;
+    }
+
+    #[tokio::test]
+    async fn bulk_requires_weight_when_configured() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, _) = create_user(&pool).await;
+        let items = [bulk_item("2024-03-01T08:00:00Z", 30)];
+
+        let without_weight = GetUserWeight { user_id, weight: None };
+        let result = insert_bulk_items(&pool, &without_weight, &items, false, REQUIRE_WEIGHT).await;
+        assert!(matches!(result, Err(AppError::BadRequest(msg)) if msg == "Item 0: Set your weight in your profile first"));
+
+        let results = insert_bulk_items(&pool, &without_weight, &items, true, REQUIRE_WEIGHT).await.unwrap();
+        assert_eq!(results[0].error.as_deref(), Some("Set your weight in your profile first"));
+        assert_eq!(activity_count(&pool, user_id).await, 0);
+
+        let with_weight = GetUserWeight { user_id, weight: Some(70.0) };
+        let results = insert_bulk_items(&pool, &with_weight, &items, false, REQUIRE_WEIGHT).await.unwrap();
+        assert!(results[0].activity_id.is_some());
+
+        delete_user(&pool, user_id).await;
+    }
+
+    #[tokio::test]
+    async fn import_applies_one_activity_per_day_by_row() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, _) = create_user(&pool).await;
+        let user = GetUserWeight { user_id, weight: None };
+        insert_bulk_items(&pool, &user, &[bulk_item("2024-03-01T08:00:00Z", 30)], false, ONE_PER_DAY).await.unwrap();
+
+        let csv = b"Running,2024-03-01T18:00:00Z,30\nRunning,2024-03-02T08:00:00Z,30\nRunning,2024-03-02T18:00:00Z,30\n";
+        let parsed = parse_import_csv(csv, UnknownTypePolicy::Reject).unwrap();
+        let (response, _) = insert_import_rows(&pool, &user, parsed, false, ONE_PER_DAY).await.unwrap();
+
+        assert_eq!(response.imported, 1);
+        let errors: Vec<_> = response.errors.iter().map(|error| (error.row, error.error.as_str())).collect();
+        assert_eq!(errors, [
+            (1, "An activity is already logged on 2024-03-01"),
+            (3, "An activity is already logged on 2024-03-02"),
+        ]);
+        assert_eq!(activity_count(&pool, user_id).await, 2);
+
+        // Strict mode fails on the first taken day and writes nothing
+        let parsed = parse_import_csv(b"Running,2024-03-05T08:00:00Z,30\nRunning,2024-03-05T09:00:00Z,30\n", UnknownTypePolicy::Reject).unwrap();
+        let result = insert_import_rows(&pool, &user, parsed, true, ONE_PER_DAY).await;
+        assert!(matches!(result, Err(AppError::BadRequest(msg)) if msg == "Row 2: An activity is already logged on 2024-03-05"));
+        assert_eq!(activity_count(&pool, user_id).await, 2);
+
+        delete_user(&pool, user_id).await;
+    }
+
+    #[tokio::test]
+    async fn import_requires_weight_when_configured() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, _) = create_user(&pool).await;
+        let csv = b"Running,2024-03-01T08:00:00Z,30\n";
+
+        let parsed = parse_import_csv(csv, UnknownTypePolicy::Reject).unwrap();
+        let result = insert_import_rows(&pool, &GetUserWeight { user_id, weight: None }, parsed, false, REQUIRE_WEIGHT).await;
+        assert!(matches!(result, Err(AppError::BadRequest(msg)) if msg == "Set your weight in your profile first"));
+
+        let parsed = parse_import_csv(csv, UnknownTypePolicy::Reject).unwrap();
+        let (response, _) = insert_import_rows(&pool, &GetUserWeight { user_id, weight: Some(70.0) }, parsed, false, REQUIRE_WEIGHT).await.unwrap();
+        assert_eq!(response.imported, 1);
+
+        delete_user(&pool, user_id).await;
+    }
+
+    #[test]
+    fn done_at_keeps_the_submitted_offset() {
+        let done_at = parse_done_at_with("2024-03-01T08:30:00+07:00", false).unwrap();
+        assert_eq!(done_at.offset().local_minus_utc(), 7 * 3600);
+        assert_eq!(done_at.with_timezone(&Utc).to_rfc3339(), "2024-03-01T01:30:00+00:00");
+    }
+
+    #[test]
+    fn naive_done_at_is_utc_unless_an_offset_is_required() {
+        let done_at = parse_done_at_with("2024-03-01T08:30:00.250", false).unwrap();
+        assert_eq!(done_at.offset().local_minus_utc(), 0);
+        assert_eq!(done_at.to_rfc3339(), "2024-03-01T08:30:00.250+00:00");
+
+        assert_eq!(parse_done_at_with("2024-03-01T08:30:00", true), Err("doneAt must include a timezone offset or Z"));
+        assert!(parse_done_at_with("2024-03-01T08:30:00Z", true).is_ok());
+    }
+
+    #[test]
+    fn malformed_done_at_is_rejected() {
+        for value in ["", "yesterday", "2024-03-01", "2024-13-01T08:30:00Z", "01/03/2024 08:30"] {
+            assert_eq!(parse_done_at_with(value, false), Err("Invalid date format"), "{}", value);
+        }
+    }
+
+    #[test]
+    fn well_formed_csv_parses_every_row() {
+        let csv = b"activityType,doneAt,durationInMinutes\nRunning,2024-03-01T08:00:00Z,30\nRunning,2024-03-02T08:00:00+07:00,45\n";
+        let parsed = parse_import_csv(csv, UnknownTypePolicy::Reject).unwrap();
+
+        assert!(parsed.errors.is_empty());
+        let rows: Vec<_> = parsed.rows.iter().map(|row| (row.row, row.activity_type.as_str(), row.duration)).collect();
+        assert_eq!(rows, [(2, "Running", 30), (3, "Running", 45)]);
+        assert_eq!(parsed.rows[1].done_at.offset().local_minus_utc(), 7 * 3600);
+    }
+
+    #[test]
+    fn bad_csv_rows_are_reported_by_line() {
+        let csv = b"Running,2024-03-01T08:00:00Z,30\nRunning,not-a-date,30\nRunning,2024-03-03T08:00:00Z\nRunning,2024-03-04T08:00:00Z,abc\n";
+        let parsed = parse_import_csv(csv, UnknownTypePolicy::Reject).unwrap();
+
+        assert_eq!(parsed.rows.len(), 1);
+        assert_eq!(parsed.rows[0].row, 1);
+        let errors: Vec<_> = parsed.errors.iter().map(|error| (error.row, error.error.as_str())).collect();
+        assert_eq!(errors, [
+            (2, "Invalid date format"),
+            (3, "Expected 3 columns, found 2"),
+            (4, "Duration must be a whole number of minutes"),
+        ]);
+    }
+
+    #[test]
+    fn unknown_csv_types_follow_the_policy() {
+        let csv = b"Running,2024-03-01T08:00:00Z,30\nUnderwaterChess,2024-03-02T08:00:00Z,30\n";
+
+        let rejected = parse_import_csv(csv, UnknownTypePolicy::Reject).unwrap();
+        assert_eq!(rejected.rows.len(), 1);
+        assert_eq!(rejected.errors.len(), 1);
+        assert_eq!(rejected.errors[0].row, 2);
+
+        let skipped = parse_import_csv(csv, UnknownTypePolicy::Skip).unwrap();
+        assert_eq!(skipped.rows.len(), 1);
+        assert!(skipped.errors.is_empty());
+        assert_eq!(skipped.unknown_types[0].activity_type, "UnderwaterChess");
+    }
+
+    #[test]
+    fn breakdown_matches_stored_computed_calories() {
+        let breakdown = calorie_breakdown(sample_activity());
+        assert_eq!(breakdown.calories_per_minute, Some(10));
+        assert_eq!(breakdown.computed_calories, Some(450));
+        assert_eq!(breakdown.computed_calories, Some(breakdown.calories_burned));
+        assert_eq!(breakdown.calories_source, CALORIES_SOURCE_COMPUTED);
+    }
+
+    #[test]
+    fn breakdown_keeps_user_supplied_calories_apart_from_the_formula() {
+        let activity = Activity {
+            calories_burned: 123,
+            calories_source: CALORIES_SOURCE_USER.to_string(),
+            ..sample_activity()
+        };
+        let breakdown = calorie_breakdown(activity);
+        assert_eq!(breakdown.calories_burned, 123);
+        assert_eq!(breakdown.computed_calories, Some(450));
+        assert_eq!(breakdown.calories_source, CALORIES_SOURCE_USER);
+    }
+
+    #[test]
+    fn breakdown_has_no_computed_value_for_unknown_types_or_overflow() {
+        let removed = Activity { activity_type: "Retired".to_string(), ..sample_activity() };
+        let breakdown = calorie_breakdown(removed);
+        assert_eq!(breakdown.calories_per_minute, None);
+        assert_eq!(breakdown.computed_calories, None);
+
+        let overflowing = Activity { duration_in_minutes: i32::MAX, ..sample_activity() };
+        assert_eq!(calorie_breakdown(overflowing).computed_calories, None);
+    }
+
+    fn links_for(uri: &str, limit: i64, offset: i64, total: i64) -> Vec<String> {
+        let req = actix_web::test::TestRequest::get().uri(uri).to_http_request();
+        pagination_links(&req, limit, offset, total).split(", ").map(str::to_string).collect()
+    }
+
+    #[test]
+    fn first_page_links_forward_only() {
+        assert_eq!(links_for("/v1/activity?limit=10&offset=0&tag=run", 10, 0, 25), [
+            "</v1/activity?tag=run&limit=10&offset=0>; rel=\"first\"",
+            "</v1/activity?tag=run&limit=10&offset=10>; rel=\"next\"",
+            "</v1/activity?tag=run&limit=10&offset=20>; rel=\"last\"",
+        ]);
+    }
+
+    #[test]
+    fn middle_page_links_both_ways() {
+        assert_eq!(links_for("/v1/activity?offset=15&limit=10", 10, 15, 25), [
+            "</v1/activity?limit=10&offset=0>; rel=\"first\"",
+            "</v1/activity?limit=10&offset=5>; rel=\"prev\"",
+            "</v1/activity?limit=10&offset=20>; rel=\"last\"",
+        ]);
+        assert!(links_for("/v1/activity", 10, 10, 25).iter().any(|link| link.ends_with("rel=\"next\"")));
+    }
+
+    #[test]
+    fn last_page_has_no_next() {
+        assert_eq!(links_for("/v1/activity?limit=10&offset=20", 10, 20, 30), [
+            "</v1/activity?limit=10&offset=0>; rel=\"first\"",
+            "</v1/activity?limit=10&offset=10>; rel=\"prev\"",
+            "</v1/activity?limit=10&offset=20>; rel=\"last\"",
+        ]);
+    }
+
+    #[test]
+    fn empty_list_links_to_the_first_page_only() {
+        assert_eq!(links_for("/v1/activity", 5, 0, 0), [
+            "</v1/activity?limit=5&offset=0>; rel=\"first\"",
+            "</v1/activity?limit=5&offset=0>; rel=\"last\"",
+        ]);
+    }
+
+    async fn list_activities(email: &str, query: &str) -> serde_json::Value {
+        let pool = test_pool().await.unwrap();
+        let req = authenticated(actix_web::test::TestRequest::get().uri(&format!("/v1/activity?{}", query)), email);
+        let response = get_activities(req, web::Data::new(pool), web::Query::from_query(query).unwrap()).await.unwrap();
+        assert_eq!(response.status(), actix_web::http::StatusCode::OK);
+        response_json(response).await
+    }
+
+    #[tokio::test]
+    async fn new_user_gets_an_empty_list() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, email) = create_user(&pool).await;
+
+        assert_eq!(list_activities(&email, "").await, serde_json::json!([]));
+        assert_eq!(
+            list_activities(&email, "envelope=true").await,
+            serde_json::json!({ "data": [], "meta": { "total": 0, "limit": 5, "offset": 0 } })
+        );
+
+        delete_user(&pool, user_id).await;
+    }
+
+    fn sample_page() -> ActivityListResponse {
+        let tagged = Activity { tags: vec!["morning".to_string()], done_at_offset: None, ..sample_activity() };
+        ActivityListResponse {
+            data: vec![sample_activity().into(), tagged.into()],
+            meta: PaginationMeta { total: 2, limit: 5, offset: 0, next_cursor: None },
+        }
+    }
+
+    #[test]
+    fn compact_rows_follow_the_column_order() {
+        let compact = CompactActivityListResponse::try_from(sample_page()).unwrap();
+
+        assert_eq!(compact.columns, ACTIVITY_COLUMNS);
+        assert_eq!(compact.rows.len(), 2);
+        assert!(compact.rows.iter().all(|row| row.len() == ACTIVITY_COLUMNS.len()));
+        assert_eq!(compact.rows[0][1], "JumpRope");
+        assert_eq!(compact.rows[0][5], "2024-03-01T07:30:00+07:00");
+        assert_eq!(compact.rows[1][5], serde_json::Value::Null);
+        assert_eq!(compact.rows[1][11], serde_json::json!(["morning"]));
+        assert_eq!(compact.meta.total, 2);
+    }
+
+    #[test]
+    fn compact_rows_round_trip_to_the_verbose_objects() {
+        let verbose = serde_json::to_value(sample_page()).unwrap();
+        let compact = CompactActivityListResponse::try_from(sample_page()).unwrap();
+
+        let zipped: Vec<serde_json::Value> = compact.rows
+            .into_iter()
+            .map(|row| ACTIVITY_COLUMNS.iter().map(|column| column.to_string()).zip(row).collect())
+            .collect();
+        assert_eq!(serde_json::Value::Array(zipped), verbose["data"]);
+        assert_eq!(serde_json::to_value(&compact.meta).unwrap(), verbose["meta"]);
+    }
+
+    #[test]
+    fn tags_are_trimmed_lowercased_and_deduplicated_in_order() {
+        let tags = normalize_tags(["  Morning ", "Run", "morning", "RUN", "long run"]).unwrap();
+        assert_eq!(tags, ["morning", "run", "long run"]);
+        assert!(normalize_tags([]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn empty_and_overlong_tags_are_rejected() {
+        assert!(matches!(normalize_tags(["ok", "   "]), Err(AppError::BadRequest(msg)) if msg == "Tags cannot be empty"));
+
+        // The limit counts characters, not bytes
+        assert!(normalize_tags(["é".repeat(MAX_TAG_LENGTH).as_str()]).is_ok());
+        let overlong = "x".repeat(MAX_TAG_LENGTH + 1);
+        assert!(matches!(
+            normalize_tags([overlong.as_str()]),
+            Err(AppError::BadRequest(msg)) if msg == "Tags cannot exceed 32 characters"
+        ));
+    }
+
+    #[test]
+    fn at_most_ten_tags_are_accepted() {
+        let with_tags = |count: usize| -> ActivityRequest {
+            let tags: Vec<String> = (0..count).map(|n| format!("tag{}", n)).collect();
+            serde_json::from_value(serde_json::json!({
+                "activityType": "Running",
+                "doneAt": "2024-03-01T08:00:00Z",
+                "durationInMinutes": 30,
+                "tags": tags,
+            }))
+            .unwrap()
+        };
+
+        assert!(with_tags(10).validate().is_ok());
+        let errors = with_tags(11).validate().unwrap_err();
+        assert_eq!(errors.field_errors()["tags"][0].message.as_deref(), Some("At most 10 tags are allowed"));
+    }
+
+    fn activity_type(calories_per_minute: i32, max_calories: Option<i32>) -> ActivityTypeInfo {
+        ActivityTypeInfo {
+            name: "Rowing".to_string(),
+            label: "Rowing".to_string(),
+            icon: String::new(),
+            category: "CARDIO".to_string(),
+            calories_per_minute,
+            met: None,
+            max_calories,
+        }
+    }
+
+    #[test]
+    fn supplied_calories_override_the_formula() {
+        let rowing = activity_type(10, None);
+        assert_eq!(resolve_calories_with(&rowing, 30, Some(123), false).unwrap(), (123, CALORIES_SOURCE_USER));
+        assert_eq!(resolve_calories_with(&rowing, 30, Some(0), true).unwrap(), (0, CALORIES_SOURCE_USER));
+        assert_eq!(resolve_calories_with(&rowing, 30, None, false).unwrap(), (300, CALORIES_SOURCE_COMPUTED));
+    }
+
+    #[test]
+    fn calories_over_the_type_cap_are_rejected() {
+        let capped = activity_type(10, Some(500));
+        assert!(resolve_calories_with(&capped, 50, None, false).is_ok());
+        assert!(matches!(
+            resolve_calories_with(&capped, 51, None, false),
+            Err(AppError::BadRequest(msg)) if msg == "Calories for Rowing cannot exceed 500"
+        ));
+        assert!(resolve_calories_with(&capped, 10, Some(501), false).is_err());
+    }
+
+    #[test]
+    fn overflowing_and_zero_formula_results_are_rejected() {
+        assert!(matches!(
+            resolve_calories_with(&activity_type(10, None), i32::MAX, None, false),
+            Err(AppError::BadRequest(msg)) if msg == "Duration is too long"
+        ));
+
+        let unconfigured = activity_type(0, None);
+        assert!(matches!(resolve_calories_with(&unconfigured, 30, None, true), Err(AppError::InternalServerError(_))));
+        assert_eq!(resolve_calories_with(&unconfigured, 30, None, false).unwrap(), (0, CALORIES_SOURCE_COMPUTED));
+    }
+
+    #[test]
+    fn unknown_types_are_rejected_before_calories() {
+        assert!(matches!(
+            resolve_calories_for("Retired", 30, Some(100)),
+            Err(AppError::BadRequest(msg)) if msg == "Invalid activity type"
+        ));
+    }
+
+    #[test]
+    fn recompute_follows_a_changed_multiplier() {
+        assert_eq!(recompute_row(Some(&activity_type(12, None)), 30, 300, false).unwrap(), Some(360));
+        assert_eq!(recompute_row(Some(&activity_type(10, None)), 30, 300, false).unwrap(), None);
+    }
+
+    #[test]
+    fn recompute_applies_the_creation_rules() {
+        assert!(recompute_row(Some(&activity_type(12, Some(320))), 30, 300, false).is_err());
+        assert!(recompute_row(Some(&activity_type(0, None)), 30, 300, true).is_err());
+        assert_eq!(recompute_row(Some(&activity_type(0, None)), 30, 300, false).unwrap(), Some(0));
+        assert!(recompute_row(Some(&activity_type(10, None)), i32::MAX, 300, false).is_err());
+        assert!(recompute_row(None, 30, 300, false).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use crate::errors::AppError;
+use crate::handlers::{activity, analytics, auth, file, profile};
+use crate::handlers::activity::{PaginationMeta, TOTAL_COUNT_HEADER};
+use crate::models::user::AdminUserProfile;
+use crate::utils::activity_types::ACTIVITY_TYPES;
+use crate::utils::jwt::{Claims, ROLE_ADMIN};
+use crate::utils::validation::{clamp_pagination, max_batch_size, DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT};
+use crate::utils::{compression, cookie, file_token, image, s3, units};
+
+#[derive(Deserialize)]
+pub struct UserListQuery {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct UserListResponse {
+    data: Vec<AdminUserProfile>,
+    meta: PaginationMeta,
+}
+
+/// Rejects callers whose account is not an admin. The role comes from users.role rather than the
+/// token's `role` claim, so a demotion applies at once, long-lived API tokens included.
+pub async fn require_admin(req: &HttpRequest, pool: &sqlx::PgPool) -> Result<(), AppError> {
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    let role = sqlx::query_scalar!("SELECT role FROM users WHERE email = $1", email)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    match role {
+        Some(role) if role == ROLE_ADMIN => Ok(()),
+        _ => Err(AppError::Forbidden("Admin role required".to_string())),
+    }
+}
+
+// GET /v1/users
+pub async fn list_users(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+    query: web::Query<UserListQuery>,
+) -> Result<HttpResponse, AppError> {
+    require_admin(&req, &pool).await?;
+
+    let (limit, offset) = clamp_pagination(query.limit, query.offset);
+
+    let total = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM users"#)
+        .fetch_one(&**pool)
+        .await
+        .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    let users = sqlx::query_as!(
+        AdminUserProfile,
+        "SELECT user_id, email, name, preference, weight_unit, height_unit, weight, height, image_uri, role, verified, last_login_at, created_at
+        FROM users ORDER BY created_at DESC, user_id DESC LIMIT $1 OFFSET $2",
+        limit,
+        offset
+    )
+    .fetch_all(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((TOTAL_COUNT_HEADER, total.to_string()))
+        .json(UserListResponse {
+            data: users,
+            meta: PaginationMeta { total, limit, offset, next_cursor: None },
+        }))
+}
+
+// Env vars holding credentials. Only whether each is set is reported, never the value.
+const SECRET_VARS: [&str; 9] = [
+    "JWT_SECRET",
+    "DATABASE_URL",
+    "AWS_ACCESS_KEY_ID",
+    "AWS_SECRET_ACCESS_KEY",
+    "PASSWORD_PEPPER",
+    "PASSWORD_PEPPER_FALLBACKS",
+    "INTROSPECTION_SECRET",
+    "INTERNAL_API_KEY",
+    "FILE_TOKEN_SECRET",
+];
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigResponse {
+    pagination: PaginationConfig,
+    limits: LimitsConfig,
+    features: FeatureConfig,
+    calories: CalorieConfig,
+    storage: StorageConfig,
+    secrets_set: BTreeMap<&'static str, bool>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PaginationConfig {
+    default_limit: i64,
+    max_limit: i64,
+    sort_direction: &'static str,
+    max_batch_size: usize,
+    recompute_batch_size: i64,
+    export_buffer_rows: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LimitsConfig {
+    max_concurrent_uploads_per_user: usize,
+    upload_chunk_timeout_seconds: u64,
+    max_image_pixels: u64,
+    max_registrations_per_ip_per_day: Option<u32>,
+    max_registrations_per_device: u32,
+    device_registration_interval_seconds: u64,
+    max_measurement_change_percent: Option<f64>,
+    max_activities_per_window: Option<u32>,
+    activity_rate_window_seconds: u64,
+    activity_retention_days: Option<i64>,
+    goal_eta_window_days: i64,
+    measurement_decimals: i32,
+    compression_min_bytes: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FeatureConfig {
+    auth_cookie: bool,
+    validate_sub_exists: bool,
+    require_email_verification: bool,
+    enforce_device_throttle: bool,
+    allow_missing_device_id: bool,
+    demo_seed_activities: bool,
+    require_weight_for_activity: bool,
+    require_tz_in_done_at: bool,
+    one_activity_per_day: bool,
+    log_query_count: bool,
+    file_download_tokens: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CalorieConfig {
+    activity_types_config: Option<String>,
+    activity_types: usize,
+    reject_zero_calories: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StorageConfig {
+    s3_profile: Option<String>,
+    bucket: Option<String>,
+    region: Option<String>,
+    // Encryption algorithm only; an invalid S3_SSE shows as its error
+    server_side_encryption: Option<String>,
+    file_token_ttl_seconds: i64,
+}
+
+// GET /v1/admin/config
+// Effective configuration after defaults and clamping, read through the same helpers the handlers
+// use. Secrets are reduced to whether they are set.
+pub async fn get_config(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<HttpResponse, AppError> {
+    require_admin(&req, &pool).await?;
+
+    let server_side_encryption = match s3::sse_config() {
+        Ok(sse) => sse.map(|sse| sse.algorithm.as_str().to_string()),
+        Err(err) => Some(format!("invalid: {}", err)),
+    };
+
+    Ok(HttpResponse::Ok().json(ConfigResponse {
+        pagination: PaginationConfig {
+            default_limit: DEFAULT_PAGE_LIMIT,
+            max_limit: MAX_PAGE_LIMIT,
+            sort_direction: activity::default_sort_direction(),
+            max_batch_size: max_batch_size(),
+            recompute_batch_size: activity::recompute_batch_size(),
+            export_buffer_rows: activity::export_buffer_rows(),
+        },
+        limits: LimitsConfig {
+            max_concurrent_uploads_per_user: file::max_concurrent_uploads(),
+            upload_chunk_timeout_seconds: file::upload_chunk_timeout().as_secs(),
+            max_image_pixels: image::max_image_pixels(),
+            max_registrations_per_ip_per_day: auth::max_registrations_per_ip(),
+            max_registrations_per_device: auth::max_registrations_per_device(),
+            device_registration_interval_seconds: auth::device_registration_interval().as_secs(),
+            max_measurement_change_percent: profile::max_measurement_change_percent(),
+            max_activities_per_window: activity::max_activities_per_window(),
+            activity_rate_window_seconds: activity::activity_rate_window().as_secs(),
+            activity_retention_days: crate::db::retention::activity_retention_days(),
+            goal_eta_window_days: analytics::goal_eta_window_days(),
+            measurement_decimals: units::measurement_decimals(),
+            compression_min_bytes: compression::compression_min_bytes(),
+        },
+        features: FeatureConfig {
+            auth_cookie: cookie::cookie_auth_enabled(),
+            validate_sub_exists: crate::utils::jwt::validate_sub_exists(),
+            require_email_verification: auth::require_email_verification(),
+            enforce_device_throttle: auth::enforce_device_throttle(),
+            allow_missing_device_id: auth::allow_missing_device_id(),
+            demo_seed_activities: auth::demo_seed_enabled(),
+            require_weight_for_activity: activity::require_weight_for_activity(),
+            require_tz_in_done_at: activity::require_tz_in_done_at(),
+            one_activity_per_day: activity::one_activity_per_day(),
+            log_query_count: crate::db::log_query_count_enabled(),
+            file_download_tokens: file_token::file_token_secret().is_some(),
+        },
+        calories: CalorieConfig {
+            activity_types_config: env::var("ACTIVITY_TYPES_CONFIG").ok().filter(|path| !path.is_empty()),
+            activity_types: ACTIVITY_TYPES.len(),
+            reject_zero_calories: activity::reject_zero_calories(),
+        },
+        storage: StorageConfig {
+            s3_profile: s3::s3_profile(),
+            bucket: s3::bucket_name(),
+            region: s3::region(),
+            server_side_encryption,
+            file_token_ttl_seconds: file_token::file_token_ttl(),
+        },
+        secrets_set: SECRET_VARS
+            .iter()
+            .map(|name| (*name, env::var(name).map(|value| !value.is_empty()).unwrap_or(false)))
+            .collect(),
+    }))
+}
@@ -0,0 +1,626 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use chrono::{Datelike, Duration, NaiveDate};
+use std::collections::BTreeMap;
+use std::env;
+use crate::models::user::{GetUserCalorieGoal, GetUserTimezone};
+use crate::errors::AppError;
+use crate::db::timed;
+use crate::utils::jwt::Claims;
+use crate::utils::timezone::resolve_timezone;
+use crate::utils::activity_types::activity_category;
+
+const DEFAULT_WEEKS: i32 = 8;
+const MAX_WEEKS: i32 = 52;
+
+// date_trunc units accepted by the category trend
+const TREND_BUCKETS: &[&str] = &["day", "week", "month"];
+
+// Longest from..to span the goal trend accepts
+const MAX_GOAL_TREND_DAYS: i64 = 366;
+
+// Longest burn-rate window the goal ETA accepts
+const MAX_GOAL_ETA_WINDOW_DAYS: i64 = 90;
+
+// Default burn-rate window for the goal ETA (GOAL_ETA_WINDOW_DAYS, default 14)
+pub fn goal_eta_window_days() -> i64 {
+    env::var("GOAL_ETA_WINDOW_DAYS")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|value| (1..=MAX_GOAL_ETA_WINDOW_DAYS).contains(value))
+        .unwrap_or(14)
+}
+
+#[derive(Deserialize)]
+pub struct WeeklyQuery {
+    weeks: Option<i32>,
+    tz: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WeeklyAggregate {
+    week_start: String,
+    total_calories: i64,
+    total_minutes: i64,
+    count: i64,
+}
+
+#[derive(Deserialize)]
+pub struct CategoryTrendQuery {
+    bucket: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CategoryTrendBucket {
+    bucket_start: String,
+    categories: BTreeMap<&'static str, i64>,
+}
+
+#[derive(Deserialize)]
+pub struct GoalTrendQuery {
+    bucket: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    tz: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoalTrendBucket {
+    bucket_start: String,
+    total_calories: i64,
+    // None when the user has no monthly goal
+    goal_calories: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct GoalEtaQuery {
+    window: Option<i64>,
+    tz: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoalEtaResponse {
+    monthly_calorie_goal: i32,
+    calories_this_month: i64,
+    remaining_calories: i64,
+    window_days: i64,
+    average_daily_calories: f64,
+    // All None when nothing was burned in the window, since the goal would never be reached
+    days_to_goal: Option<i64>,
+    projected_date: Option<String>,
+    on_track: Option<bool>,
+}
+
+// GET /v1/activity/weekly
+pub async fn get_weekly_aggregates(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+    query: web::Query<WeeklyQuery>,
+) -> Result<HttpResponse, AppError> {
+    let weeks = query.weeks.unwrap_or(DEFAULT_WEEKS);
+    if !(1..=MAX_WEEKS).contains(&weeks) {
+        return Err(AppError::BadRequest(format!("Weeks must be between 1 and {}", MAX_WEEKS)));
+    }
+
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    // Fetch user from database
+    let user = sqlx::query_as!(
+        GetUserTimezone,
+        "SELECT user_id, timezone FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let timezone = resolve_timezone(query.tz.as_deref(), user.timezone.as_deref())?;
+
+    // Generate every week in the window so weeks without activities come back as zeros
+    let rows = timed("activities.weekly", sqlx::query!(
+        r#"
+        WITH weeks AS (
+            SELECT generate_series(
+                date_trunc('week', NOW() AT TIME ZONE $2) - ($3::int - 1) * INTERVAL '1 week',
+                date_trunc('week', NOW() AT TIME ZONE $2),
+                INTERVAL '1 week'
+            ) AS week_start
+        )
+        SELECT
+            w.week_start AS "week_start!",
+            COALESCE(SUM(a.calories_burned), 0)::BIGINT AS "total_calories!",
+            COALESCE(SUM(a.duration_in_minutes), 0)::BIGINT AS "total_minutes!",
+            COUNT(a.activity_id) AS "count!"
+        FROM weeks w
+        LEFT JOIN activities a
+            ON a.user_id = $1
+            AND date_trunc('week', a.done_at AT TIME ZONE $2) = w.week_start
+        GROUP BY w.week_start
+        ORDER BY w.week_start
+        "#,
+        user.user_id,
+        timezone.pg_name(),
+        weeks
+    )
+    .fetch_all(&**pool))
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    let aggregates: Vec<WeeklyAggregate> = rows
+        .into_iter()
+        .map(|row| WeeklyAggregate {
+            week_start: row.week_start.date().to_string(),
+            total_calories: row.total_calories,
+            total_minutes: row.total_minutes,
+            count: row.count,
+        })
+        .collect();
+
+    // Return response
+    Ok(HttpResponse::Ok().json(aggregates))
+}
+
+fn parse_trend_date(field: &str, value: &str) -> Result<NaiveDate, AppError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| AppError::BadRequest(format!("Invalid {} date, expected YYYY-MM-DD", field)))
+}
+
+// GET /v1/activity/category-trend
+pub async fn get_category_trend(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+    query: web::Query<CategoryTrendQuery>,
+) -> Result<HttpResponse, AppError> {
+    let bucket = query.bucket.as_deref().unwrap_or("week");
+    if !TREND_BUCKETS.contains(&bucket) {
+        return Err(AppError::BadRequest("Bucket must be one of day, week or month".to_string()));
+    }
+
+    let from = query.from.as_deref().map(|value| parse_trend_date("from", value)).transpose()?;
+    let to = query.to.as_deref().map(|value| parse_trend_date("to", value)).transpose()?;
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err(AppError::BadRequest("From must not be after to".to_string()));
+        }
+    }
+
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    // Fetch user from database
+    let user = sqlx::query_as!(
+        GetUserTimezone,
+        "SELECT user_id, timezone FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let timezone = resolve_timezone(query.tz.as_deref(), user.timezone.as_deref())?;
+
+    // from/to are inclusive local calendar days
+    let done_from = from.map(|date| timezone.day_bounds_utc(date).0);
+    let done_before = to.map(|date| timezone.day_bounds_utc(date).1);
+
+    // Categories live in the activity type registry, so the database groups by type and the
+    // per-category sums are folded together below
+    let rows = timed("activities.category_trend", sqlx::query!(
+        r#"
+        SELECT
+            date_trunc($2, done_at AT TIME ZONE $3) AS "bucket_start!",
+            activity_type,
+            SUM(calories_burned)::BIGINT AS "total_calories!"
+        FROM activities
+        WHERE user_id = $1
+            AND ($4::timestamptz IS NULL OR done_at >= $4)
+            AND ($5::timestamptz IS NULL OR done_at < $5)
+        GROUP BY 1, activity_type
+        ORDER BY 1
+        "#,
+        user.user_id,
+        bucket,
+        timezone.pg_name(),
+        done_from,
+        done_before
+    )
+    .fetch_all(&**pool))
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    let mut trend: Vec<CategoryTrendBucket> = Vec::new();
+    for row in rows {
+        let bucket_start = row.bucket_start.date().to_string();
+        if trend.last().map(|last| last.bucket_start != bucket_start).unwrap_or(true) {
+            trend.push(CategoryTrendBucket { bucket_start, categories: BTreeMap::new() });
+        }
+        if let Some(current) = trend.last_mut() {
+            *current.categories.entry(activity_category(&row.activity_type)).or_insert(0) += row.total_calories;
+        }
+    }
+
+    // Return response
+    Ok(HttpResponse::Ok().json(trend))
+}
+
+fn days_in_month(date: NaiveDate) -> i64 {
+    let first = date.with_day(1).unwrap_or(date);
+    let next = if first.month() == 12 {
+        NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(first.year(), first.month() + 1, 1)
+    };
+    next.map(|next| (next - first).num_days()).unwrap_or(30)
+}
+
+// Share of the monthly goal for the days in [start, end). Each day counts 1/days-in-its-month,
+// so weeks spanning two months are prorated against both.
+fn prorated_goal(monthly_goal: i32, start: NaiveDate, end: NaiveDate) -> i64 {
+    let mut goal = 0.0;
+    let mut day = start;
+    while day < end {
+        goal += monthly_goal as f64 / days_in_month(day) as f64;
+        day += Duration::days(1);
+    }
+    goal.round() as i64
+}
+
+// First day of the bucket after the one starting at `start`
+fn next_bucket_start(bucket: &str, start: NaiveDate) -> NaiveDate {
+    match bucket {
+        "day" => start + Duration::days(1),
+        "week" => start + Duration::days(7),
+        _ => start + Duration::days(days_in_month(start)),
+    }
+}
+
+// GET /v1/activity/goal-trend
+pub async fn get_goal_trend(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+    query: web::Query<GoalTrendQuery>,
+) -> Result<HttpResponse, AppError> {
+    let bucket = query.bucket.as_deref().unwrap_or("week");
+    if !TREND_BUCKETS.contains(&bucket) {
+        return Err(AppError::BadRequest("Bucket must be one of day, week or month".to_string()));
+    }
+
+    let (from, to) = match (query.from.as_deref(), query.to.as_deref()) {
+        (Some(from), Some(to)) => (parse_trend_date("from", from)?, parse_trend_date("to", to)?),
+        _ => return Err(AppError::BadRequest("From and to are required".to_string())),
+    };
+    if from > to {
+        return Err(AppError::BadRequest("From must not be after to".to_string()));
+    }
+    if (to - from).num_days() >= MAX_GOAL_TREND_DAYS {
+        return Err(AppError::BadRequest(format!("Range must not exceed {} days", MAX_GOAL_TREND_DAYS)));
+    }
+
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    // Fetch user from database
+    let user = sqlx::query_as!(
+        GetUserCalorieGoal,
+        "SELECT user_id, timezone, monthly_calorie_goal FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let timezone = resolve_timezone(query.tz.as_deref(), user.timezone.as_deref())?;
+
+    // Every bucket touching from..to, whole periods, so empty buckets come back as zeros
+    let rows = timed("activities.goal_trend", sqlx::query!(
+        r#"
+        WITH buckets AS (
+            SELECT generate_series(
+                date_trunc($2, $4::date::timestamp),
+                date_trunc($2, $5::date::timestamp),
+                ('1 ' || $2)::interval
+            ) AS bucket_start
+        )
+        SELECT
+            b.bucket_start AS "bucket_start!",
+            COALESCE(SUM(a.calories_burned), 0)::BIGINT AS "total_calories!"
+        FROM buckets b
+        LEFT JOIN activities a
+            ON a.user_id = $1
+            AND date_trunc($2, a.done_at AT TIME ZONE $3) = b.bucket_start
+        GROUP BY b.bucket_start
+        ORDER BY b.bucket_start
+        "#,
+        user.user_id,
+        bucket,
+        timezone.pg_name(),
+        from,
+        to
+    )
+    .fetch_all(&**pool))
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    let trend: Vec<GoalTrendBucket> = rows
+        .into_iter()
+        .map(|row| {
+            let start = row.bucket_start.date();
+            GoalTrendBucket {
+                bucket_start: start.to_string(),
+                total_calories: row.total_calories,
+                goal_calories: user.monthly_calorie_goal
+                    .map(|goal| prorated_goal(goal, start, next_bucket_start(bucket, start))),
+            }
+        })
+        .collect();
+
+    // Return response
+    Ok(HttpResponse::Ok().json(trend))
+}
+
+// GET /v1/activity/goal-eta
+// Projects when this month's goal is reached at the average daily burn over the last `window`
+// local days, today included
+pub async fn get_goal_eta(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+    query: web::Query<GoalEtaQuery>,
+) -> Result<HttpResponse, AppError> {
+    let window_days = match query.window {
+        Some(window) if (1..=MAX_GOAL_ETA_WINDOW_DAYS).contains(&window) => window,
+        Some(_) => return Err(AppError::BadRequest(format!("Window must be between 1 and {} days", MAX_GOAL_ETA_WINDOW_DAYS))),
+        None => goal_eta_window_days(),
+    };
+
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    // Fetch user from database
+    let user = sqlx::query_as!(
+        GetUserCalorieGoal,
+        "SELECT user_id, timezone, monthly_calorie_goal FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let monthly_goal = user.monthly_calorie_goal
+        .ok_or_else(|| AppError::BadRequest("Set a monthly calorie goal in your profile first".to_string()))?;
+
+    let timezone = resolve_timezone(query.tz.as_deref(), user.timezone.as_deref())?;
+    let today = timezone.today();
+    let month_start = today.with_day(1).unwrap_or(today);
+    let window_start = today - Duration::days(window_days - 1);
+    let (_, end) = timezone.day_bounds_utc(today);
+    let (window_from, _) = timezone.day_bounds_utc(window_start);
+    let (month_from, _) = timezone.day_bounds_utc(month_start);
+
+    let totals = timed("activities.goal_eta", sqlx::query!(
+        r#"
+        SELECT
+            COALESCE(SUM(calories_burned) FILTER (WHERE done_at >= $2), 0)::BIGINT AS "window_calories!",
+            COALESCE(SUM(calories_burned) FILTER (WHERE done_at >= $3), 0)::BIGINT AS "month_calories!"
+        FROM activities
+        WHERE user_id = $1 AND done_at >= LEAST($2, $3) AND done_at < $4
+        "#,
+        user.user_id,
+        window_from,
+        month_from,
+        end
+    )
+    .fetch_one(&**pool))
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    let remaining = (monthly_goal as i64 - totals.month_calories).max(0);
+    let average = totals.window_calories as f64 / window_days as f64;
+    let days_to_goal = if remaining == 0 {
+        Some(0)
+    } else if average > 0.0 {
+        Some((remaining as f64 / average).ceil() as i64)
+    } else {
+        None
+    };
+    let projected = days_to_goal.map(|days| today + Duration::days(days));
+    let month_end = month_start + Duration::days(days_in_month(month_start) - 1);
+
+    // Return response
+    Ok(HttpResponse::Ok().json(GoalEtaResponse {
+        monthly_calorie_goal: monthly_goal,
+        calories_this_month: totals.month_calories,
+        remaining_calories: remaining,
+        window_days,
+        average_daily_calories: (average * 100.0).round() / 100.0,
+        days_to_goal,
+        projected_date: projected.map(|date| date.to_string()),
+        on_track: projected.map(|date| date <= month_end),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+    use crate::test_support::{authenticated_request, create_user, delete_user, insert_activity, response_json, test_pool};
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn months_have_their_calendar_length() {
+        assert_eq!(days_in_month(date(2024, 1, 15)), 31);
+        assert_eq!(days_in_month(date(2024, 4, 30)), 30);
+        assert_eq!(days_in_month(date(2024, 12, 31)), 31);
+        assert_eq!(days_in_month(date(2024, 2, 1)), 29);
+        assert_eq!(days_in_month(date(2023, 2, 1)), 28);
+    }
+
+    #[test]
+    fn partial_month_gets_its_share_of_the_goal() {
+        assert_eq!(prorated_goal(3000, date(2024, 4, 1), date(2024, 4, 11)), 1000);
+        assert_eq!(prorated_goal(3100, date(2024, 1, 1), date(2024, 1, 8)), 700);
+        assert_eq!(prorated_goal(3000, date(2024, 4, 5), date(2024, 4, 5)), 0);
+    }
+
+    #[test]
+    fn buckets_spanning_two_months_are_prorated_against_both() {
+        assert_eq!(prorated_goal(3100, date(2024, 1, 29), date(2024, 2, 1)), 300);
+        assert_eq!(prorated_goal(2900, date(2024, 2, 1), date(2024, 2, 5)), 400);
+        // Three days at 3000/31 plus four at 3000/29
+        assert_eq!(prorated_goal(3000, date(2024, 1, 29), date(2024, 2, 5)), 704);
+    }
+
+    #[test]
+    fn leap_february_is_one_whole_goal() {
+        assert_eq!(prorated_goal(2900, date(2024, 2, 1), next_bucket_start("month", date(2024, 2, 1))), 2900);
+        assert_eq!(prorated_goal(2800, date(2023, 2, 1), next_bucket_start("month", date(2023, 2, 1))), 2800);
+        assert_eq!(next_bucket_start("month", date(2024, 2, 1)), date(2024, 3, 1));
+        assert_eq!(next_bucket_start("week", date(2024, 2, 26)), date(2024, 3, 4));
+        assert_eq!(next_bucket_start("day", date(2024, 2, 29)), date(2024, 3, 1));
+    }
+
+    #[tokio::test]
+    async fn goal_trend_prorates_the_goal_or_leaves_it_null() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, email) = create_user(&pool).await;
+        insert_activity(&pool, user_id, "Running", Utc.with_ymd_and_hms(2024, 1, 3, 8, 0, 0).unwrap(), 250).await;
+
+        let trend = |email: String| {
+            let pool = pool.clone();
+            async move {
+                let query = web::Query::from_query("from=2024-01-01&to=2024-01-14&bucket=week").unwrap();
+                response_json(get_goal_trend(authenticated_request(&email), web::Data::new(pool), query).await.unwrap()).await
+            }
+        };
+
+        assert_eq!(trend(email.clone()).await, json!([
+            { "bucketStart": "2024-01-01", "totalCalories": 250, "goalCalories": null },
+            { "bucketStart": "2024-01-08", "totalCalories": 0, "goalCalories": null },
+        ]));
+
+        sqlx::query("UPDATE users SET monthly_calorie_goal = 3100 WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(trend(email).await, json!([
+            { "bucketStart": "2024-01-01", "totalCalories": 250, "goalCalories": 700 },
+            { "bucketStart": "2024-01-08", "totalCalories": 0, "goalCalories": 700 },
+        ]));
+
+        delete_user(&pool, user_id).await;
+    }
+
+    #[tokio::test]
+    async fn weekly_aggregates_fill_empty_weeks_with_zeros() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, email) = create_user(&pool).await;
+        let now = Utc::now();
+        insert_activity(&pool, user_id, "Running", now, 300).await;
+        insert_activity(&pool, user_id, "Yoga", now, 120).await;
+        insert_activity(&pool, user_id, "Running", now - Duration::weeks(2), 200).await;
+        // Outside the four requested weeks
+        insert_activity(&pool, user_id, "Running", now - Duration::weeks(6), 999).await;
+
+        let query = web::Query::from_query("weeks=4").unwrap();
+        let weeks = response_json(get_weekly_aggregates(authenticated_request(&email), web::Data::new(pool.clone()), query).await.unwrap()).await;
+        let weeks = weeks.as_array().unwrap();
+
+        assert_eq!(weeks.len(), 4);
+        let totals: Vec<(i64, i64, i64)> = weeks
+            .iter()
+            .map(|week| (week["totalCalories"].as_i64().unwrap(), week["totalMinutes"].as_i64().unwrap(), week["count"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(totals, [(0, 0, 0), (200, 30, 1), (0, 0, 0), (420, 60, 2)]);
+
+        // Consecutive Mondays ending with the current week
+        let starts: Vec<NaiveDate> = weeks.iter().map(|week| parse_trend_date("weekStart", week["weekStart"].as_str().unwrap()).unwrap()).collect();
+        assert!(starts.iter().all(|start| start.weekday() == chrono::Weekday::Mon));
+        assert!(starts.windows(2).all(|pair| pair[1] - pair[0] == Duration::weeks(1)));
+        assert_eq!(starts[3], now.date_naive().week(chrono::Weekday::Mon).first_day());
+
+        delete_user(&pool, user_id).await;
+    }
+
+    #[tokio::test]
+    async fn weeks_out_of_bounds_are_rejected() {
+        for weeks in ["0", "53"] {
+            let query = web::Query::from_query(&format!("weeks={}", weeks)).unwrap();
+            // Bounds are checked before the database is touched
+            let pool = sqlx::PgPool::connect_lazy("postgres://nobody@127.0.0.1:1/none").unwrap();
+            let result = get_weekly_aggregates(authenticated_request("nobody@example.com"), web::Data::new(pool), query).await;
+            assert!(matches!(result, Err(AppError::BadRequest(msg)) if msg == "Weeks must be between 1 and 52"));
+        }
+    }
+
+    async fn category_trend(pool: &sqlx::PgPool, email: &str, query: &str) -> serde_json::Value {
+        let query = web::Query::from_query(query).unwrap();
+        response_json(get_category_trend(authenticated_request(email), web::Data::new(pool.clone()), query).await.unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn category_trend_sums_per_bucket_and_category() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, email) = create_user(&pool).await;
+        let at = |month, day, hour| Utc.with_ymd_and_hms(2024, month, day, hour, 0, 0).unwrap();
+        insert_activity(&pool, user_id, "Running", at(1, 1, 8), 300).await;
+        insert_activity(&pool, user_id, "Cycling", at(1, 3, 8), 200).await;
+        insert_activity(&pool, user_id, "Yoga", at(1, 3, 18), 100).await;
+        insert_activity(&pool, user_id, "Yoga", at(1, 9, 8), 50).await;
+        insert_activity(&pool, user_id, "Running", at(2, 1, 8), 400).await;
+
+        assert_eq!(category_trend(&pool, &email, "bucket=week&from=2024-01-01&to=2024-01-31").await, json!([
+            { "bucketStart": "2024-01-01", "categories": { "CARDIO": 500, "FLEXIBILITY": 100 } },
+            { "bucketStart": "2024-01-08", "categories": { "FLEXIBILITY": 50 } },
+        ]));
+        assert_eq!(category_trend(&pool, &email, "bucket=month").await, json!([
+            { "bucketStart": "2024-01-01", "categories": { "CARDIO": 500, "FLEXIBILITY": 150 } },
+            { "bucketStart": "2024-02-01", "categories": { "CARDIO": 400 } },
+        ]));
+        assert_eq!(category_trend(&pool, &email, "bucket=day&from=2024-01-03&to=2024-01-03").await, json!([
+            { "bucketStart": "2024-01-03", "categories": { "CARDIO": 200, "FLEXIBILITY": 100 } },
+        ]));
+
+        delete_user(&pool, user_id).await;
+    }
+
+    #[tokio::test]
+    async fn category_trend_buckets_in_the_requested_timezone() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, email) = create_user(&pool).await;
+        // Sunday evening in UTC is already Monday in Jakarta
+        insert_activity(&pool, user_id, "Running", Utc.with_ymd_and_hms(2024, 1, 7, 20, 0, 0).unwrap(), 300).await;
+
+        assert_eq!(category_trend(&pool, &email, "bucket=week").await, json!([
+            { "bucketStart": "2024-01-01", "categories": { "CARDIO": 300 } },
+        ]));
+        assert_eq!(category_trend(&pool, &email, "bucket=week&tz=Asia/Jakarta").await, json!([
+            { "bucketStart": "2024-01-08", "categories": { "CARDIO": 300 } },
+        ]));
+
+        delete_user(&pool, user_id).await;
+    }
+}
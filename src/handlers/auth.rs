@@ -1,20 +1,146 @@
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use chrono::Utc;
-use bcrypt::{hash, verify};
+use bcrypt::hash;
 use jsonwebtoken::{encode, Header, EncodingKey};
 use validator::Validate;
 use std::env;
-use crate::utils::jwt::Claims;
+use crate::utils::jwt::{forget_subject, validate_token_async, Claims, ROLE_USER, SCOPE_API, SCOPE_CHANGE_EMAIL, SCOPE_SESSION, SCOPE_VERIFY_EMAIL};
+use crate::utils::cookie::{cookie_auth_enabled, AuthCookieConfig};
+use crate::utils::password::{apply_pepper, validate_not_common_password, validate_password_not_email, verify_with_pepper_fallbacks, PepperMatch};
 use crate::models::user;
+use crate::handlers::activity::seed_demo_activities;
 use crate::errors::AppError;
 use actix_web::rt::task::spawn_blocking;
 use lazy_static::lazy_static;
 use moka::sync::Cache;
+use log::{debug, error};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use std::time::Duration;
+use crate::utils::client_ip::client_ip;
+use crate::utils::window_counter::{Reservation, WindowCounter};
 
 lazy_static! {
-    static ref EMAIL_CACHE: Cache<String, bool> = Cache::new(10_000); //Important, the load test only got like 200 emails and took resource, may cause test fail if removed
+    // Emails known to be registered, so repeat sign-ups skip bcrypt. Only a shortcut: the INSERT ...
+    // ON CONFLICT stays the source of truth, so an expired or evicted entry just costs one hash.
+    // Keyed on the email exactly as stored, since users.email is case-sensitive.
+    static ref EMAIL_CACHE: Cache<String, bool> = Cache::builder()
+        .max_capacity(10_000)
+        .time_to_live(email_cache_ttl())
+        .build(); //Important, the load test only got like 200 emails and took resource, may cause test fail if removed
+
+    // Registrations in flight per email. A burst for one address waits for the first attempt and
+    // then hits EMAIL_CACHE instead of hashing again; idle entries expire quickly.
+    static ref REGISTRATIONS_IN_FLIGHT: Cache<String, Arc<Mutex<()>>> = Cache::builder()
+        .max_capacity(10_000)
+        .time_to_idle(Duration::from_secs(30))
+        .build();
+
+    // Successful registrations per client IP, the window starts at the first registration
+    static ref REGISTRATIONS_PER_IP: WindowCounter<String> = WindowCounter::new(Duration::from_secs(24 * 60 * 60));
+
+    // Successful registrations per X-Device-Id, the window starts at the first registration
+    static ref REGISTRATIONS_PER_DEVICE: WindowCounter<String> = WindowCounter::new(device_registration_interval());
+
+    // Verified against when the email is unknown so both login failures cost one bcrypt verify
+    static ref DUMMY_PASSWORD_HASH: String = hash("fitbyte-dummy-password", BCRYPT_COST)
+        .expect("Failed to hash dummy password");
+}
+
+const BCRYPT_COST: u32 = 10;
+
+/// Computes the dummy login hash at startup so the first unknown-email login is not slower.
+pub fn init_dummy_password_hash() {
+    lazy_static::initialize(&DUMMY_PASSWORD_HASH);
+}
+
+// How long a registered email is remembered by EMAIL_CACHE (EMAIL_CACHE_TTL_SECONDS, default 600)
+fn email_cache_ttl() -> Duration {
+    Duration::from_secs(
+        env::var("EMAIL_CACHE_TTL_SECONDS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(600),
+    )
+}
+
+// When enabled, new accounts start with a few sample activities
+pub fn demo_seed_enabled() -> bool {
+    env::var("DEMO_SEED_ACTIVITIES").map(|v| v == "true").unwrap_or(false)
+}
+
+// When enabled, new accounts must verify their email before they can log in
+pub fn require_email_verification() -> bool {
+    env::var("REQUIRE_EMAIL_VERIFICATION").map(|v| v == "true").unwrap_or(false)
+}
+
+// Unlimited unless MAX_REGISTRATIONS_PER_IP_PER_DAY is set
+pub fn max_registrations_per_ip() -> Option<u32> {
+    env::var("MAX_REGISTRATIONS_PER_IP_PER_DAY")
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+}
+
+const DEVICE_ID_HEADER: &str = "X-Device-Id";
+
+// Longest device id kept as a cache key; anything longer is rejected rather than truncated
+const MAX_DEVICE_ID_LENGTH: usize = 128;
+
+pub fn enforce_device_throttle() -> bool {
+    env::var("ENFORCE_DEVICE_THROTTLE").map(|v| v == "true").unwrap_or(false)
+}
+
+// Registrations allowed per device within the interval (MAX_REGISTRATIONS_PER_DEVICE, default 1)
+pub fn max_registrations_per_device() -> u32 {
+    env::var("MAX_REGISTRATIONS_PER_DEVICE")
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(1)
+}
+
+// DEVICE_REGISTRATION_INTERVAL_SECONDS, default one hour
+pub fn device_registration_interval() -> Duration {
+    Duration::from_secs(
+        env::var("DEVICE_REGISTRATION_INTERVAL_SECONDS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|value| *value > 0)
+            .unwrap_or(60 * 60),
+    )
+}
+
+// Whether a registration without X-Device-Id goes through (DEVICE_ID_MISSING_POLICY, allow or deny)
+pub fn allow_missing_device_id() -> bool {
+    env::var("DEVICE_ID_MISSING_POLICY").map(|v| v != "deny").unwrap_or(true)
+}
+
+// Reserves a per-device registration slot when ENFORCE_DEVICE_THROTTLE is on, None when not
+// throttled. The slot is handed back if the registration fails.
+fn reserve_device_registration(http_req: &HttpRequest) -> Result<Option<Reservation>, AppError> {
+    if !enforce_device_throttle() {
+        return Ok(None);
+    }
+
+    let device_id = http_req.headers()
+        .get(DEVICE_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty());
+    let device_id = match device_id {
+        Some(device_id) if device_id.len() > MAX_DEVICE_ID_LENGTH => {
+            return Err(AppError::BadRequest(format!("{} is too long", DEVICE_ID_HEADER)));
+        }
+        Some(device_id) => device_id,
+        None if allow_missing_device_id() => return Ok(None),
+        None => return Err(AppError::BadRequest(format!("{} header is required", DEVICE_ID_HEADER))),
+    };
+
+    REGISTRATIONS_PER_DEVICE.reserve(device_id.to_string(), 1, max_registrations_per_device())
+        .map(Some)
+        .map_err(|quota| AppError::RateLimited("Too many registrations from this device, try again later".to_string(), quota))
 }
 
 #[derive(Deserialize, Validate)]
@@ -26,12 +152,66 @@ pub struct AuthRequest {
     password: String,
 }
 
+#[derive(Deserialize, Validate)]
+pub struct ApiTokenRequest {
+    #[validate(length(min = 1, message = "Password is required"))]
+    password: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokenResponse {
+    token: String,
+    scope: String,
+    expires_at: String,
+}
+
 #[derive(Serialize)]
 pub struct AuthResponse {
     email: String,
+    // Omitted at registration when email verification is required
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
     token: String,
 }
 
+#[derive(Deserialize, Validate)]
+pub struct ChangeEmailRequest {
+    #[validate(email(message = "Invalid email format"))]
+    email: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeEmailResponse {
+    email: String,
+    pending: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+// Re-hashes a password that matched a previous pepper with the current one
+async fn upgrade_password_hash(pool: &PgPool, email: &str, password: String) -> Result<(), AppError> {
+    let new_hash = spawn_blocking(move || hash(apply_pepper(&password), BCRYPT_COST))
+        .await
+        .map_err(|_| AppError::InternalServerError("Hashing failed".to_string()))?
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+
+    sqlx::query!(
+        "UPDATE users SET password = $1 WHERE email = $2",
+        new_hash,
+        email
+    )
+    .execute(pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+    Ok(())
+}
+
 // POST /v1/login
 pub async fn login(
     req: web::Json<AuthRequest>,
@@ -42,33 +222,67 @@ pub async fn login(
 
     // Fetch user from database
     let user = sqlx::query_as!(
-        user::GetUserPassword,
-        "SELECT password FROM users WHERE email = $1",
+        user::GetUserLogin,
+        "SELECT password, verified, role FROM users WHERE email = $1",
         req.email
     )
     .fetch_optional(&**pool)
     .await
-    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
-    .ok_or_else(|| AppError::NotFound("Email not found".to_string()))?;
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
 
     let req_email = req.email.clone();
+    let user_exists = user.is_some();
+    let verified = user.as_ref().map(|user| user.verified).unwrap_or(false);
+    let role = user.as_ref().map(|user| user.role.clone()).unwrap_or_default();
 
-    // Verify password using bcrypt
-    let is_valid = spawn_blocking(move || verify(req.password.as_str(), &user.password))
+    // Verify password using bcrypt. Unknown emails are checked against a dummy hash so the
+    // response time and body do not reveal whether the account exists.
+    let password_hash = match user {
+        Some(user) => user.password,
+        None => DUMMY_PASSWORD_HASH.clone(),
+    };
+    let password = req.password.clone();
+    let pepper_match = spawn_blocking(move || verify_with_pepper_fallbacks(&password, &password_hash))
         .await
         .map_err(|_| AppError::InternalServerError("Password verification error".to_string()))?
         .map_err(|e| AppError::InternalServerError(e.to_string()))?;   
 
 
-    if !is_valid {
-        return Err(AppError::Unauthorized("Invalid password".to_string()));
+    if pepper_match == PepperMatch::NoMatch || !user_exists {
+        return Err(AppError::Unauthorized("Invalid email or password".to_string()));
+    }
+
+    if pepper_match == PepperMatch::Fallback {
+        upgrade_password_hash(&pool, &req_email, req.password.clone()).await?;
+    }
+
+    if !verified {
+        return Err(AppError::Forbidden("Email not verified".to_string()));
     }
 
+    // Record the login time in the background, a failure here must not fail the login
+    let login_pool = pool.clone();
+    let login_email = req_email.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = sqlx::query!(
+            "UPDATE users SET last_login_at = NOW() WHERE email = $1",
+            login_email
+        )
+        .execute(&**login_pool)
+        .await
+        {
+            error!("Failed to update last_login_at: {}", e);
+        }
+    });
+
     // Generate JWT token using spawn_blocking
     let jwt_secret = env::var("JWT_SECRET").unwrap();
     let claims = Claims {
         sub: req_email.clone(),
         exp: (Utc::now() + chrono::Duration::days(7)).timestamp() as usize,
+        scope: SCOPE_SESSION.to_string(),
+        role,
+        iat: Utc::now().timestamp() as usize,
     };
 
     let token = spawn_blocking(move || {
@@ -83,22 +297,49 @@ pub async fn login(
     .map_err(|_| AppError::InternalServerError("Token generation error".to_string()))?;
 
     // Return response
-    Ok(HttpResponse::Ok().json(AuthResponse {
+    let mut response = HttpResponse::Ok();
+    if cookie_auth_enabled() {
+        let cookie_config = AuthCookieConfig::from_env().map_err(AppError::InternalServerError)?;
+        response.cookie(cookie_config.build(token.clone(), chrono::Duration::days(7)));
+    }
+
+    Ok(response.json(AuthResponse {
         email: req_email,
-        token,
+        token: Some(token),
     }))
 }
 
 // POST /v1/register
 pub async fn register(
+    http_req: HttpRequest,
     req: web::Json<AuthRequest>,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, AppError> {
     // Validate request
     req.validate()
         .map_err(|err| AppError::BadRequest(err.to_string()))?;
+    validate_not_common_password(&req.password)?;
+    validate_password_not_email(&req.password, &req.email)?;
+
+    // Throttle registrations per client IP. The slot is taken now and handed back if the
+    // registration fails, so a parallel burst cannot slip past the limit.
+    let ip_reservation = match (max_registrations_per_ip(), client_ip(&http_req)) {
+        (Some(max), Some(ip)) => Some(REGISTRATIONS_PER_IP.reserve(ip, 1, max).map_err(|quota| {
+            AppError::RateLimited("Too many registrations from this IP, try again later".to_string(), quota)
+        })?),
+        _ => None,
+    };
+    let device_reservation = reserve_device_registration(&http_req)?;
+
+    if EMAIL_CACHE.contains_key(&req.email) {
+        return Err(AppError::Conflict("Email exists (cached)".to_string()));
+    }
 
-    if EMAIL_CACHE.get(&req.email).is_some() {
+    // Concurrent attempts for the same email run one at a time; later ones usually stop at the
+    // cache check below once the first has committed
+    let in_flight = REGISTRATIONS_IN_FLIGHT.get_with(req.email.clone(), || Arc::new(Mutex::new(())));
+    let _registration_guard = in_flight.lock().await;
+    if EMAIL_CACHE.contains_key(&req.email) {
         return Err(AppError::Conflict("Email exists (cached)".to_string()));
     }
 
@@ -106,7 +347,7 @@ pub async fn register(
     let email = req.email.clone();
 
     // Handle bcrypt hashing result properly
-    let password_hash = spawn_blocking(move || hash(&password, 10))
+    let password_hash = spawn_blocking(move || hash(apply_pepper(&password), BCRYPT_COST))
         .await
         .map_err(|_| AppError::InternalServerError("Hashing failed".to_string()))?
         .map_err(|e| AppError::InternalServerError(e.to_string()))?; // Unwrap bcrypt result
@@ -115,16 +356,23 @@ pub async fn register(
         .await
         .map_err(|_| AppError::InternalServerError("UUID generation failed".to_string()))?;
 
-    // Insert and check if email already exists
+    // Insert and check if email already exists, demo activities are seeded in the same transaction
+    let verification_required = require_email_verification();
+    let mut tx = pool.begin()
+        .await
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+
     let result = sqlx::query!(
-        "INSERT INTO users (user_id, email, password, created_at, updated_at) 
-        VALUES ($1, $2, $3, NOW(), NOW())
+        "INSERT INTO users (user_id, email, password, verified, created_at, updated_at, tokens_valid_after) 
+        VALUES ($1, $2, $3, $4, NOW(), NOW(), $5)
         ON CONFLICT (email) DO NOTHING",
         user_id,
         email,
-        password_hash // Direct String value
+        password_hash, // Direct String value
+        !verification_required,
+        Utc::now() // App clock, like the `iat` it is compared with
     )
-    .execute(&**pool)
+    .execute(&mut *tx)
     .await;
 
     // Check if email already exists
@@ -134,10 +382,52 @@ pub async fn register(
     };
 
     if rows_affected == 0 {
+        EMAIL_CACHE.insert(req.email.clone(), true);
         return Err(AppError::Conflict("Email already exists".to_string()));
     }
 
+    if demo_seed_enabled() {
+        seed_demo_activities(&mut tx, user_id).await?;
+    }
+
+    tx.commit()
+        .await
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+
     EMAIL_CACHE.insert(req.email.clone(), true);
+    // The address may have belonged to another account whose lookup is still cached
+    forget_subject(&req.email);
+    for reservation in [ip_reservation, device_reservation].into_iter().flatten() {
+        reservation.commit(1);
+    }
+
+    // Unverified accounts get a verification token delivered out of band instead of a session
+    if verification_required {
+        let verification_token = spawn_blocking(move || {
+            encode(
+                &Header::default(),
+                &Claims {
+                    sub: email,
+                    exp: (Utc::now() + chrono::Duration::hours(24)).timestamp() as usize,
+                    scope: SCOPE_VERIFY_EMAIL.to_string(),
+                    role: ROLE_USER.to_string(),
+                    iat: Utc::now().timestamp() as usize,
+                },
+                &EncodingKey::from_secret(env::var("JWT_SECRET").unwrap().as_bytes()),
+            )
+        })
+        .await
+        .map_err(|_| AppError::InternalServerError("Token generation failed".to_string()))?
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+
+        // There is no mailer yet, so the token is only surfaced in debug logs
+        debug!("Email verification token for {}: {}", req.email, verification_token);
+
+        return Ok(HttpResponse::Created().json(AuthResponse {
+            email: req.email.clone(),
+            token: None,
+        }));
+    }
 
     // Generate JWT token
     let token = spawn_blocking(move || {
@@ -146,6 +436,9 @@ pub async fn register(
             &Claims {
                 sub: email,
                 exp: (Utc::now() + chrono::Duration::hours(1)).timestamp() as usize,
+                scope: SCOPE_SESSION.to_string(),
+                role: ROLE_USER.to_string(),
+                iat: Utc::now().timestamp() as usize,
             },
             &EncodingKey::from_secret(env::var("JWT_SECRET").unwrap().as_bytes()),
         )
@@ -155,8 +448,375 @@ pub async fn register(
     .map_err(|e| AppError::InternalServerError(e.to_string()))?;
 
     // Return response
-    Ok(HttpResponse::Created().json(AuthResponse {
+    let mut response = HttpResponse::Created();
+    if cookie_auth_enabled() {
+        let cookie_config = AuthCookieConfig::from_env().map_err(AppError::InternalServerError)?;
+        response.cookie(cookie_config.build(token.clone(), chrono::Duration::hours(1)));
+    }
+
+    Ok(response.json(AuthResponse {
         email: req.email.clone(),
+        token: Some(token),
+    }))
+}
+
+// POST /v1/verify-email
+pub async fn verify_email(
+    req: web::Json<VerifyEmailRequest>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let jwt_secret = env::var("JWT_SECRET").unwrap();
+    let claims = validate_token_async(&req.token, &jwt_secret)
+        .await
+        .map_err(|_| AppError::BadRequest("Invalid or expired verification token".to_string()))?;
+
+    if claims.scope != SCOPE_VERIFY_EMAIL {
+        return Err(AppError::BadRequest("Invalid or expired verification token".to_string()));
+    }
+
+    let result = sqlx::query!(
+        "UPDATE users SET verified = TRUE WHERE email = $1",
+        claims.sub
+    )
+    .execute(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    // Return response
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "email": claims.sub, "verified": true })))
+}
+
+// POST /v1/user/api-token
+pub async fn issue_api_token(
+    http_req: HttpRequest,
+    req: web::Json<ApiTokenRequest>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    // Validate request
+    req.validate().map_err(|err| AppError::BadRequest(err.to_string()))?;
+
+    let email = http_req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    // Fetch user from database. The role is read here rather than copied from the session, since
+    // the token outlives it by months.
+    let user = sqlx::query!(
+        "SELECT password, role FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    // Step-up auth: the password must be re-entered, checked the same way as at login
+    let password = req.password.clone();
+    let password_hash = user.password;
+    let pepper_match = spawn_blocking(move || verify_with_pepper_fallbacks(&password, &password_hash))
+        .await
+        .map_err(|_| AppError::InternalServerError("Password verification error".to_string()))?
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+
+    match pepper_match {
+        PepperMatch::NoMatch => return Err(AppError::Unauthorized("Invalid password".to_string())),
+        PepperMatch::Fallback => upgrade_password_hash(&pool, &email, req.password.clone()).await?,
+        PepperMatch::Current => {}
+    }
+
+    let expiry_days = env::var("API_TOKEN_EXPIRY_DAYS")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .unwrap_or(90);
+    let expires_at = Utc::now() + chrono::Duration::days(expiry_days);
+
+    // Generate JWT token using spawn_blocking
+    let jwt_secret = env::var("JWT_SECRET").unwrap();
+    let claims = Claims {
+        sub: email,
+        exp: expires_at.timestamp() as usize,
+        scope: SCOPE_API.to_string(),
+        role: user.role,
+        iat: Utc::now().timestamp() as usize,
+    };
+
+    let token = spawn_blocking(move || {
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(jwt_secret.as_bytes()),
+        )
+    })
+    .await
+    .map_err(|_| AppError::InternalServerError("Token generation error".to_string()))?
+    .map_err(|_| AppError::InternalServerError("Token generation error".to_string()))?;
+
+    // Return response
+    Ok(HttpResponse::Created().json(ApiTokenResponse {
         token,
+        scope: SCOPE_API.to_string(),
+        expires_at: expires_at.to_rfc3339(),
     }))
-}
\ No newline at end of file
+}
+
+// Issues a 7-day session token, as login does
+async fn issue_session_token(email: String, role: String) -> Result<String, AppError> {
+    let jwt_secret = env::var("JWT_SECRET").unwrap();
+    let claims = Claims {
+        sub: email,
+        exp: (Utc::now() + chrono::Duration::days(7)).timestamp() as usize,
+        scope: SCOPE_SESSION.to_string(),
+        role,
+        iat: Utc::now().timestamp() as usize,
+    };
+
+    spawn_blocking(move || {
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(jwt_secret.as_bytes()),
+        )
+    })
+    .await
+    .map_err(|_| AppError::InternalServerError("Token generation error".to_string()))?
+    .map_err(|_| AppError::InternalServerError("Token generation error".to_string()))
+}
+
+// Moves an account to a new email and returns its role; a unique violation means the address
+// was taken meanwhile
+async fn apply_email_change(pool: &PgPool, old_email: &str, new_email: &str) -> Result<String, AppError> {
+    let role = sqlx::query_scalar!(
+        "UPDATE users SET email = $1, pending_email = NULL, tokens_valid_after = $3 WHERE email = $2 RETURNING role",
+        new_email,
+        old_email,
+        Utc::now() // App clock, like the `iat` it is compared with
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| match e.as_database_error().and_then(|db| db.code()) {
+        Some(code) if code == "23505" => AppError::Conflict("Email already exists".to_string()),
+        _ => AppError::InternalServerError("Database error".to_string()),
+    })?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    EMAIL_CACHE.invalidate(old_email);
+    EMAIL_CACHE.insert(new_email.to_string(), true);
+    forget_subject(old_email);
+    forget_subject(new_email);
+    Ok(role)
+}
+
+// Applies the change parked for `email` by change_email, returning the new address and the role
+async fn confirm_pending_email(pool: &PgPool, email: &str) -> Result<(String, String), AppError> {
+    let user = sqlx::query!(
+        "SELECT pending_email FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let new_email = user.pending_email
+        .ok_or_else(|| AppError::BadRequest("No pending email change".to_string()))?;
+
+    let role = apply_email_change(pool, email, &new_email).await?;
+    Ok((new_email, role))
+}
+
+// POST /v1/user/email
+//
+// With REVOKE_TOKENS_ON_EMAIL_CHANGE, applying the change revokes every token issued so far, so
+// the client has to use the token returned here or log in again.
+pub async fn change_email(
+    http_req: HttpRequest,
+    req: web::Json<ChangeEmailRequest>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    // Validate request
+    req.validate().map_err(|err| AppError::BadRequest(err.to_string()))?;
+    // Stored exactly as given, like register does, since users.email is case-sensitive
+    let new_email = req.email.clone();
+
+    let email = http_req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    if new_email == email {
+        return Err(AppError::BadRequest("New email must differ from the current one".to_string()));
+    }
+
+    let taken = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM users WHERE email = $1) AS "exists!""#,
+        new_email
+    )
+    .fetch_one(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    if taken {
+        return Err(AppError::Conflict("Email already exists".to_string()));
+    }
+
+    if !require_email_verification() {
+        let role = apply_email_change(&pool, &email, &new_email).await?;
+        let token = issue_session_token(new_email.clone(), role).await?;
+
+        return Ok(HttpResponse::Ok().json(ChangeEmailResponse {
+            email: new_email,
+            pending: false,
+            token: Some(token),
+        }));
+    }
+
+    // Park the new address until the confirmation token comes back
+    let result = sqlx::query!(
+        "UPDATE users SET pending_email = $1 WHERE email = $2",
+        new_email,
+        email
+    )
+    .execute(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    let confirmation_claims = Claims {
+        sub: email,
+        exp: (Utc::now() + chrono::Duration::hours(24)).timestamp() as usize,
+        scope: SCOPE_CHANGE_EMAIL.to_string(),
+        role: ROLE_USER.to_string(),
+        iat: Utc::now().timestamp() as usize,
+    };
+    let confirmation_token = spawn_blocking(move || {
+        encode(
+            &Header::default(),
+            &confirmation_claims,
+            &EncodingKey::from_secret(env::var("JWT_SECRET").unwrap().as_bytes()),
+        )
+    })
+    .await
+    .map_err(|_| AppError::InternalServerError("Token generation failed".to_string()))?
+    .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+
+    // There is no mailer yet, so the token is only surfaced in debug logs
+    debug!("Email change confirmation token for {}: {}", new_email, confirmation_token);
+
+    Ok(HttpResponse::Accepted().json(ChangeEmailResponse {
+        email: new_email,
+        pending: true,
+        token: None,
+    }))
+}
+
+// POST /v1/user/email/confirm
+pub async fn confirm_email_change(
+    req: web::Json<VerifyEmailRequest>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let jwt_secret = env::var("JWT_SECRET").unwrap();
+    let claims = validate_token_async(&req.token, &jwt_secret)
+        .await
+        .map_err(|_| AppError::BadRequest("Invalid or expired confirmation token".to_string()))?;
+
+    if claims.scope != SCOPE_CHANGE_EMAIL {
+        return Err(AppError::BadRequest("Invalid or expired confirmation token".to_string()));
+    }
+
+    let (new_email, role) = confirm_pending_email(&pool, &claims.sub).await?;
+    let token = issue_session_token(new_email.clone(), role).await?;
+
+    Ok(HttpResponse::Ok().json(ChangeEmailResponse {
+        email: new_email,
+        pending: false,
+        token: Some(token),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{authenticated_request, create_user, delete_user, test_pool};
+    use crate::utils::jwt::verify_subject;
+
+    #[tokio::test]
+    async fn change_email_rejects_a_taken_address() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, email) = create_user(&pool).await;
+        let (other_id, other_email) = create_user(&pool).await;
+
+        let result = change_email(
+            authenticated_request(&email),
+            web::Json(ChangeEmailRequest { email: other_email.clone() }),
+            web::Data::new(pool.clone()),
+        )
+        .await;
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+
+        // The same conflict when the address is taken between the check and the update
+        assert!(matches!(apply_email_change(&pool, &email, &other_email).await, Err(AppError::Conflict(_))));
+
+        delete_user(&pool, user_id).await;
+        delete_user(&pool, other_id).await;
+    }
+
+    #[tokio::test]
+    async fn confirming_moves_the_account_to_the_pending_address() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, email) = create_user(&pool).await;
+        let new_email = format!("moved-{}", email);
+        sqlx::query("UPDATE users SET pending_email = $1 WHERE user_id = $2")
+            .bind(&new_email)
+            .bind(user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let (confirmed, role) = confirm_pending_email(&pool, &email).await.unwrap();
+        assert_eq!(confirmed, new_email);
+        assert_eq!(role, ROLE_USER);
+
+        let (stored, pending): (String, Option<String>) =
+            sqlx::query_as("SELECT email, pending_email FROM users WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(stored, new_email);
+        assert_eq!(pending, None);
+
+        // Nothing left to confirm under either address
+        assert!(matches!(confirm_pending_email(&pool, &email).await, Err(AppError::NotFound(_))));
+        assert!(matches!(confirm_pending_email(&pool, &new_email).await, Err(AppError::BadRequest(_))));
+
+        delete_user(&pool, user_id).await;
+    }
+
+    #[tokio::test]
+    async fn tokens_issued_before_a_change_are_revoked() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, email) = create_user(&pool).await;
+        let new_email = format!("moved-{}", email);
+        let before_change = (Utc::now().timestamp() - 60) as usize;
+
+        apply_email_change(&pool, &email, &new_email).await.unwrap();
+        let after_change = Utc::now().timestamp() as usize;
+
+        // The old address is gone, and the account now at the new one predates the old token
+        assert!(verify_subject(&pool, &email, before_change, false, true).await.is_err());
+        assert!(verify_subject(&pool, &new_email, before_change, false, true).await.is_err());
+        assert!(verify_subject(&pool, &new_email, after_change, false, true).await.is_ok());
+        // Revocation is opt-in
+        assert!(verify_subject(&pool, &new_email, before_change, false, false).await.is_ok());
+
+        delete_user(&pool, user_id).await;
+    }
+}
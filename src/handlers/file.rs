@@ -1,54 +1,196 @@
-use actix_web::{web, HttpResponse, HttpRequest, Error};
+use actix_web::{web, http::header, HttpMessage, HttpResponse, HttpRequest, Error};
 use aws_sdk_s3::Client as S3Client;
-use uuid::Uuid;
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use sha2::{Digest, Sha256};
 use std::env;
 use serde_json::json;
 use actix_multipart::Multipart;
-use futures_util::StreamExt;
+use futures_util::{Stream, StreamExt};
 use tokio::spawn;
+use tokio::time::timeout;
+use std::time::Duration;
 use log::{info, error};
 use infer;
+use crate::utils::s3::{bucket_name, parse_s3_uri, sse_config, S3_BREAKER};
+use crate::errors::AppError;
+use aws_sdk_s3::primitives::DateTimeFormat;
+use serde::{Deserialize, Serialize};
+use crate::utils::validation::sanitize_filename;
+use crate::utils::image::{validate_aspect_ratio, validate_pixel_count};
+use crate::utils::jwt::Claims;
+use crate::utils::file_token::{file_token_secret, file_token_ttl, sign_file_token, verify_file_token};
+use crate::utils::metrics::{S3_PUT_DURATION_SECONDS, UPLOAD_FILE_BYTES};
+use lazy_static::lazy_static;
+use moka::sync::Cache;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use sqlx::PgPool;
 
-pub async fn upload_file(
-    req: HttpRequest,
-    s3_client: web::Data<S3Client>,
-    payload: web::Payload,
-) -> Result<HttpResponse, Error> {
-    info!("Received file upload request");
+// Largest accepted file, in bytes
+const MAX_FILE_SIZE: usize = 102400;
+
+// Room for multipart boundaries and part headers on top of the file itself
+const MULTIPART_OVERHEAD: usize = 4096;
+
+lazy_static! {
+    // In-flight upload slots per user, keyed by the caller's email; idle entries are evicted so the map stays small
+    static ref UPLOAD_SLOTS: Cache<String, Arc<Semaphore>> = Cache::builder()
+        .time_to_idle(Duration::from_secs(60 * 60))
+        .build();
+}
 
-    let mut multipart = Multipart::new(&req.headers(), payload);
+pub fn max_concurrent_uploads() -> usize {
+    env::var("MAX_CONCURRENT_UPLOADS_PER_USER")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(2)
+}
+
+pub fn upload_chunk_timeout() -> Duration {
+    Duration::from_secs(
+        env::var("UPLOAD_CHUNK_TIMEOUT_SECONDS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|value| *value > 0)
+            .unwrap_or(10),
+    )
+}
+
+// Next item of a multipart stream, failing if the client sends nothing for `limit`
+async fn next_within<S: Stream + Unpin>(stream: &mut S, limit: Duration) -> Result<Option<S::Item>, AppError> {
+    timeout(limit, stream.next()).await.map_err(|_| {
+        error!("Upload stalled for {}s", limit.as_secs());
+        AppError::RequestTimeout("Upload stalled".to_string())
+    })
+}
+
+// Collects the bytes of the "file" multipart field. Each read is bounded by
+// UPLOAD_CHUNK_TIMEOUT_SECONDS, on top of the overall UPLOAD_TIMEOUT_SECONDS deadline.
+async fn read_file_field(multipart: &mut Multipart) -> Result<Vec<u8>, AppError> {
     let mut file_data = Vec::new();
     let mut file_size = 0;
+    let chunk_timeout = upload_chunk_timeout();
 
-    // Collect file data
-    while let Some(item) = multipart.next().await {
+    while let Some(item) = next_within(multipart, chunk_timeout).await? {
         let mut field = item.map_err(|err| {
             error!("Invalid multipart field: {:?}", err);
-            actix_web::error::ErrorBadRequest("Invalid multipart field")
+            AppError::BadRequest("Invalid multipart field".to_string())
         })?;
 
         if field.name() != "file" {
             error!("Invalid field name: expected 'file'");
-            return Err(actix_web::error::ErrorBadRequest("Invalid field name: expected 'file'"));
+            return Err(AppError::BadRequest("Invalid field name: expected 'file'".to_string()));
         }
 
-        while let Some(chunk) = field.next().await {
+        // The object key is always content-hash based; the client name is only ever used sanitized
+        if let Some(original_name) = field.content_disposition().get_filename() {
+            info!("Original file name: {}", sanitize_filename(original_name));
+        }
+
+        while let Some(chunk) = next_within(&mut field, chunk_timeout).await? {
             let chunk = chunk.map_err(|err| {
                 error!("Failed to read chunk: {:?}", err);
-                actix_web::error::ErrorBadRequest("Failed to read chunk")
+                AppError::BadRequest("Failed to read chunk".to_string())
             })?;
             file_size += chunk.len();
-            if file_size > 102400 {
+            if file_size > MAX_FILE_SIZE {
                 error!("File size exceeds 100KiB limit");
-                return Err(actix_web::error::ErrorBadRequest("File size exceeds 100KiB limit"));
+                return Err(AppError::BadRequest("File size exceeds 100KiB limit".to_string()));
             }
             file_data.extend_from_slice(&chunk);
         }
     }
 
+    Ok(file_data)
+}
+
+// Records that the account behind `email` uploaded `key`, so it may later check or share it
+async fn record_upload(pool: &PgPool, email: &str, key: &str) -> Result<(), AppError> {
+    sqlx::query!(
+        "INSERT INTO user_files (user_id, file_key) SELECT user_id, $2 FROM users WHERE email = $1 ON CONFLICT DO NOTHING",
+        email,
+        key
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        error!("Failed to record upload: {:?}", e);
+        AppError::InternalServerError("Database error".to_string())
+    })?;
+    Ok(())
+}
+
+// Whether the account behind `email` uploaded `key`. Its profile image counts too, since images
+// set before uploads were recorded have no user_files row.
+async fn owns_file(pool: &PgPool, email: &str, uri: &str, key: &str) -> Result<bool, AppError> {
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS(
+            SELECT 1 FROM users u
+            WHERE u.email = $1
+              AND (u.image_uri = $2 OR EXISTS(SELECT 1 FROM user_files f WHERE f.user_id = u.user_id AND f.file_key = $3))
+        ) AS "exists!""#,
+        email,
+        uri,
+        key
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))
+}
+
+// The caller's email, from the claims the auth middleware attached
+fn caller_email(req: &HttpRequest) -> Result<String, AppError> {
+    req.extensions().get::<Claims>().map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))
+}
+
+pub async fn upload_file(
+    req: HttpRequest,
+    s3_client: web::Data<S3Client>,
+    pool: web::Data<PgPool>,
+    payload: web::Payload,
+) -> Result<HttpResponse, AppError> {
+    info!("Received file upload request");
+
+    // Reject an oversized declared body before reading any of it. The chunk-level check in
+    // read_file_field still applies for clients that lie about or omit the length.
+    let declared_length = req.headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared_length.is_some_and(|length| length > MAX_FILE_SIZE + MULTIPART_OVERHEAD) {
+        error!("Declared Content-Length exceeds 100KiB limit");
+        return Err(AppError::BadRequest("File size exceeds 100KiB limit".to_string()));
+    }
+
+    let email = caller_email(&req)?;
+
+    // Held until the handler returns, on success or error
+    let slots = UPLOAD_SLOTS.get_with(email.clone(), || Arc::new(Semaphore::new(max_concurrent_uploads())));
+    let _permit = slots.try_acquire_owned().map_err(|_| {
+        error!("Too many concurrent uploads for user {}", email);
+        AppError::TooManyRequests("Too many concurrent uploads".to_string())
+    })?;
+
+    let mut multipart = Multipart::new(req.headers(), payload);
+
+    // Bound the whole body read so a slow client cannot hold a worker indefinitely
+    let upload_timeout = env::var("UPLOAD_TIMEOUT_SECONDS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(30);
+    let file_data = timeout(Duration::from_secs(upload_timeout), read_file_field(&mut multipart))
+        .await
+        .map_err(|_| {
+            error!("Upload body not received within {}s", upload_timeout);
+            AppError::RequestTimeout("Upload timed out".to_string())
+        })??;
+    let file_size = file_data.len();
+
     if file_data.is_empty() {
         error!("File part is missing");
-        return Err(actix_web::error::ErrorBadRequest("File part is missing"));
+        return Err(AppError::BadRequest("File part is missing".to_string()));
     }
 
     info!("File size: {}", file_size);
@@ -56,55 +198,326 @@ pub async fn upload_file(
     // Detect file type
     let file_type = infer::get(&file_data).ok_or_else(|| {
         error!("Unable to detect file type");
-        actix_web::error::ErrorBadRequest("Unable to detect file type")
+        AppError::BadRequest("Unable to detect file type".to_string())
     })?;
 
     info!("Detected file type: {:?}", file_type.mime_type());
 
     if !["image/jpeg", "image/jpg", "image/png"].contains(&file_type.mime_type()) {
         error!("Only JPEG, JPG, and PNG files are allowed");
-        return Err(actix_web::error::ErrorBadRequest("Only JPEG, JPG, and PNG files are allowed"));
+        return Err(AppError::BadRequest("Only JPEG, JPG, and PNG files are allowed".to_string()));
     }
 
-    // Generate a unique file name using UUID
-    let file_id = Uuid::new_v4();
-    let file_name = format!("{}.{}", file_id, file_type.extension());
+    // Guard against image bombs, then the optional avatar shape constraint
+    validate_pixel_count(&file_data, file_type.mime_type())
+        .and_then(|_| validate_aspect_ratio(&file_data, file_type.mime_type()))
+        .map_err(|err| {
+            error!("Rejected image: {}", err);
+            err
+        })?;
+
+    // Name the object after its content so identical uploads share one object
+    let file_hash: String = Sha256::digest(&file_data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let file_name = format!("{}.{}", file_hash, file_type.extension());
 
     // Generate the S3 URI
-    let bucket_name = env::var("AWS_S3_BUCKET").map_err(|_| {
+    let bucket_name = bucket_name().ok_or_else(|| {
         error!("AWS_S3_BUCKET environment variable not set");
-        actix_web::error::ErrorInternalServerError("AWS_S3_BUCKET not set")
+        AppError::InternalServerError("AWS_S3_BUCKET not set".to_string())
     })?;
     let s3_uri = format!("s3://{}/{}", bucket_name, file_name);
 
     info!("Uploading file to S3: {}", s3_uri);
 
+    let sse = sse_config().map_err(|err| {
+        error!("Invalid server-side encryption config: {}", err);
+        AppError::InternalServerError("Invalid server-side encryption config".to_string())
+    })?;
+
+    let Some(breaker) = S3_BREAKER.allow_request() else {
+        error!("S3 circuit breaker is open, rejecting upload");
+        return Err(AppError::InternalServerError("Storage temporarily unavailable".to_string()));
+    };
+
+    // Skip the put when the same content was uploaded before
+    match s3_client.head_object().bucket(&bucket_name).key(&file_name).send().await {
+        Ok(_) => {
+            breaker.success();
+            record_upload(&pool, &email, &file_name).await?;
+            UPLOAD_FILE_BYTES.with_label_values(&[file_type.mime_type()]).observe(file_size as f64);
+            info!("File already stored, reusing {}", s3_uri);
+            return Ok(HttpResponse::Ok().json(json!({ "uri": s3_uri })));
+        }
+        Err(err) => {
+            let service_error = err.into_service_error();
+            if !service_error.is_not_found() {
+                breaker.failure();
+                error!("Failed to check for an existing S3 object: {:?}", service_error);
+                return Err(AppError::InternalServerError("Failed to upload to S3".to_string()));
+            }
+        }
+    }
+
     // Upload the file to S3
     let s3_client_clone = s3_client.clone();
 
+    let object_key = file_name.clone();
     let upload_task = spawn(async move {
-        s3_client_clone.put_object()
+        let _put_timer = S3_PUT_DURATION_SECONDS.start_timer();
+        let mut request = s3_client_clone.put_object()
             .bucket(&bucket_name)
-            .key(&file_name)
-            .body(file_data.into())
-            .send()
-            .await
+            .key(&object_key)
+            .body(file_data.into());
+
+        if let Some(sse) = sse {
+            request = request
+                .server_side_encryption(sse.algorithm)
+                .set_ssekms_key_id(sse.kms_key_id);
+        }
+
+        request.send().await
     });
 
     // Await the upload task
     match upload_task.await {
         Ok(Ok(_)) => {
             // Successfully uploaded to S3
+            breaker.success();
+            record_upload(&pool, &email, &file_name).await?;
+            UPLOAD_FILE_BYTES.with_label_values(&[file_type.mime_type()]).observe(file_size as f64);
             info!("File uploaded to S3 successfully");
             Ok(HttpResponse::Ok().json(json!({ "uri": s3_uri })))
         }
         Ok(Err(err)) => {
+            breaker.failure();
             error!("Failed to upload to S3: {:?}", err);
-            Err(actix_web::error::ErrorInternalServerError("Failed to upload to S3"))
+            Err(AppError::InternalServerError("Failed to upload to S3".to_string()))
         }
         Err(err) => {
+            breaker.failure();
             error!("Upload task failed: {:?}", err);
-            Err(actix_web::error::ErrorServiceUnavailable("Upload task failed"))
+            Err(AppError::ServiceUnavailable("Upload task failed".to_string()))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct FileQuery {
+    uri: String,
+}
+
+// HEAD /v1/file?uri=s3://bucket/key
+// Objects the caller never uploaded answer 404 like missing ones, so content hashes cannot be probed
+pub async fn head_file(
+    req: HttpRequest,
+    s3_client: web::Data<S3Client>,
+    pool: web::Data<PgPool>,
+    query: web::Query<FileQuery>,
+) -> Result<HttpResponse, AppError> {
+    let bucket_name = bucket_name().ok_or_else(|| {
+        error!("AWS_S3_BUCKET environment variable not set");
+        AppError::InternalServerError("AWS_S3_BUCKET not set".to_string())
+    })?;
+
+    // Only objects in our own bucket can be checked
+    let key = match parse_s3_uri(&query.uri) {
+        Some((bucket, key)) if bucket == bucket_name => key.to_string(),
+        _ => return Err(AppError::BadRequest("Invalid file URI".to_string())),
+    };
+
+    if !owns_file(&pool, &caller_email(&req)?, &query.uri, &key).await? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let Some(breaker) = S3_BREAKER.allow_request() else {
+        return Err(AppError::InternalServerError("Storage temporarily unavailable".to_string()));
+    };
+
+    match s3_client.head_object().bucket(&bucket_name).key(&key).send().await {
+        Ok(output) => {
+            breaker.success();
+            let mut response = HttpResponse::Ok();
+            if let Some(last_modified) = output.last_modified().and_then(|t| t.fmt(DateTimeFormat::HttpDate).ok()) {
+                response.insert_header(("Last-Modified", last_modified));
+            }
+
+            // An empty body would force Content-Length: 0, so mirror the object's length on an
+            // unchunked empty stream instead
+            if let Some(length) = output.content_length() {
+                response.no_chunking(length as u64);
+            }
+            Ok(response.streaming(futures_util::stream::empty::<Result<web::Bytes, Error>>()))
+        }
+        Err(err) => {
+            let service_error = err.into_service_error();
+            if service_error.is_not_found() {
+                // A missing object is a healthy answer from S3
+                breaker.success();
+                return Ok(HttpResponse::NotFound().finish());
+            }
+            breaker.failure();
+            error!("Failed to head S3 object: {:?}", service_error);
+            Err(AppError::InternalServerError("Failed to check file".to_string()))
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadTokenResponse {
+    token: String,
+    expires_at: String,
+}
+
+// GET /v1/file/token?uri=s3://bucket/key
+// Short-lived, HMAC-signed link to one of the caller's uploads that can be shared without credentials
+pub async fn issue_download_token(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    query: web::Query<FileQuery>,
+) -> Result<HttpResponse, AppError> {
+    let secret = file_token_secret()
+        .ok_or_else(|| AppError::NotFound("File download tokens are not enabled".to_string()))?;
+
+    let bucket_name = bucket_name().ok_or_else(|| {
+        error!("AWS_S3_BUCKET environment variable not set");
+        AppError::InternalServerError("AWS_S3_BUCKET not set".to_string())
+    })?;
+
+    // Only objects in our own bucket can be shared
+    let key = match parse_s3_uri(&query.uri) {
+        Some((bucket, key)) if bucket == bucket_name => key,
+        _ => return Err(AppError::BadRequest("Invalid file URI".to_string())),
+    };
+
+    // Only the uploader may share an object
+    if !owns_file(&pool, &caller_email(&req)?, &query.uri, key).await? {
+        return Err(AppError::NotFound("File not found".to_string()));
+    }
+
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(file_token_ttl());
+    Ok(HttpResponse::Ok().json(DownloadTokenResponse {
+        token: sign_file_token(key, expires_at.timestamp(), &secret),
+        expires_at: expires_at.to_rfc3339(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct DownloadQuery {
+    token: String,
+}
+
+// Only a single `bytes=` range is forwarded to S3, which does not support multiple ranges.
+// Anything else is ignored and the whole file is sent, as RFC 9110 allows.
+fn is_single_byte_range(value: &str) -> bool {
+    let Some((start, end)) = value.strip_prefix("bytes=").and_then(|spec| spec.trim().split_once('-')) else {
+        return false;
+    };
+    let is_digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    match (start.is_empty(), end.is_empty()) {
+        (false, true) => is_digits(start),
+        (true, false) => is_digits(end),
+        (false, false) => is_digits(start) && is_digits(end)
+            && matches!((start.parse::<u64>(), end.parse::<u64>()), (Ok(first), Ok(last)) if first <= last),
+        (true, true) => false,
+    }
+}
+
+// GET /v1/file/download?token=...
+// Public; the token is the only credential, so tampered and expired tokens look the same
+pub async fn download_file(
+    req: HttpRequest,
+    s3_client: web::Data<S3Client>,
+    query: web::Query<DownloadQuery>,
+) -> Result<HttpResponse, AppError> {
+    let secret = file_token_secret()
+        .ok_or_else(|| AppError::NotFound("File download tokens are not enabled".to_string()))?;
+    let key = verify_file_token(&query.token, &secret, chrono::Utc::now().timestamp())?;
+
+    let bucket_name = bucket_name().ok_or_else(|| {
+        error!("AWS_S3_BUCKET environment variable not set");
+        AppError::InternalServerError("AWS_S3_BUCKET not set".to_string())
+    })?;
+
+    let Some(breaker) = S3_BREAKER.allow_request() else {
+        return Err(AppError::InternalServerError("Storage temporarily unavailable".to_string()));
+    };
+
+    let range = req.headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_single_byte_range(value));
+
+    let output = match s3_client.get_object().bucket(&bucket_name).key(&key).set_range(range.map(str::to_string)).send().await {
+        Ok(output) => {
+            breaker.success();
+            output
+        }
+        Err(err) => {
+            let service_error = err.into_service_error();
+            if service_error.is_no_such_key() {
+                breaker.success();
+                return Err(AppError::NotFound("File not found".to_string()));
+            }
+            if service_error.code() == Some("InvalidRange") {
+                breaker.success();
+                return Err(AppError::RangeNotSatisfiable("Requested range is outside the file".to_string()));
+            }
+            breaker.failure();
+            error!("Failed to get S3 object: {:?}", service_error);
+            return Err(AppError::InternalServerError("Failed to download file".to_string()));
+        }
+    };
+
+    // S3 echoes Content-Range only when it served a range, so that decides between 206 and 200
+    let mut response = match output.content_range() {
+        Some(content_range) => {
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header((header::CONTENT_RANGE, content_range.to_string()));
+            response
+        }
+        None => HttpResponse::Ok(),
+    };
+    response.insert_header((header::ACCEPT_RANGES, "bytes"));
+    if let Some(content_type) = output.content_type() {
+        response.content_type(content_type.to_string());
+    }
+    if let Some(length) = output.content_length() {
+        response.no_chunking(length as u64);
+    }
+
+    // Relay the object as S3 sends it instead of buffering it
+    let body = futures_util::stream::unfold(output.body, |mut body| async move {
+        body.next().await.map(|chunk| {
+            let chunk = chunk.map_err(|err| {
+                error!("Failed to read S3 object body: {:?}", err);
+                Error::from(AppError::InternalServerError("Failed to download file".to_string()))
+            });
+            (chunk, body)
+        })
+    });
+    Ok(response.streaming(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_byte_ranges_are_forwarded() {
+        assert!(is_single_byte_range("bytes=0-499"));
+        assert!(is_single_byte_range("bytes=500-"));
+        assert!(is_single_byte_range("bytes=-500"));
+        assert!(is_single_byte_range("bytes=7-7"));
+    }
+
+    #[test]
+    fn other_range_values_are_ignored() {
+        for value in ["bytes=0-99,200-299", "bytes=-", "bytes=9-3", "bytes=a-b", "items=0-9", "bytes=0-99999999999999999999", ""] {
+            assert!(!is_single_byte_range(value), "{value:?}");
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,109 @@
+use actix_web::{web, HttpResponse};
+use aws_sdk_s3::Client as S3Client;
+use lazy_static::lazy_static;
+use log::error;
+use serde::Serialize;
+use std::env;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use crate::utils::s3::{bucket_name, S3_BREAKER};
+
+#[derive(Serialize, Clone)]
+pub struct ReadinessResponse {
+    status: &'static str,
+    database: &'static str,
+    storage: &'static str,
+}
+
+lazy_static! {
+    // Last readiness result; the async lock also makes concurrent probes wait for one check
+    static ref READINESS_CACHE: Mutex<Option<(Instant, ReadinessResponse)>> = Mutex::new(None);
+}
+
+fn readiness_cache_ttl() -> Duration {
+    Duration::from_millis(
+        env::var("READINESS_CACHE_TTL_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(2000),
+    )
+}
+
+async fn check_dependencies(pool: &sqlx::PgPool, s3_client: &S3Client) -> ReadinessResponse {
+    let database = match sqlx::query("SELECT 1").execute(pool).await {
+        Ok(_) => "ok",
+        Err(err) => {
+            error!("Readiness database check failed: {}", err);
+            "unavailable"
+        }
+    };
+
+    let storage = match bucket_name().zip(S3_BREAKER.allow_request()) {
+        Some((bucket_name, breaker)) => {
+            match s3_client.head_bucket().bucket(&bucket_name).send().await {
+                Ok(_) => {
+                    breaker.success();
+                    "ok"
+                }
+                Err(err) => {
+                    breaker.failure();
+                    error!("Readiness storage check failed: {:?}", err);
+                    "unavailable"
+                }
+            }
+        }
+        _ => "unavailable",
+    };
+
+    let status = if database == "ok" && storage == "ok" { "ready" } else { "not_ready" };
+    ReadinessResponse { status, database, storage }
+}
+
+// GET /ready
+pub async fn ready(
+    pool: web::Data<sqlx::PgPool>,
+    s3_client: web::Data<S3Client>,
+) -> HttpResponse {
+    let mut cache = READINESS_CACHE.lock().await;
+
+    let readiness = match cache.as_ref() {
+        Some((checked_at, readiness)) if checked_at.elapsed() < readiness_cache_ttl() => readiness.clone(),
+        _ => {
+            let readiness = check_dependencies(&pool, &s3_client).await;
+            *cache = Some((Instant::now(), readiness.clone()));
+            readiness
+        }
+    };
+    drop(cache);
+
+    if readiness.status == "ready" {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionResponse {
+    version: &'static str,
+    commit: &'static str,
+    built_at: Option<String>,
+}
+
+// GET /v1/version
+pub async fn version() -> HttpResponse {
+    // BUILD_* are set by build.rs
+    let built_at = env!("BUILD_TIMESTAMP")
+        .parse::<i64>()
+        .ok()
+        .filter(|secs| *secs > 0)
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|at| at.to_rfc3339());
+
+    HttpResponse::Ok().json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: env!("BUILD_GIT_COMMIT"),
+        built_at,
+    })
+}
@@ -0,0 +1,119 @@
+use actix_web::{web, HttpResponse};
+use actix_web_httpauth::extractors::basic::BasicAuth;
+use serde::{Deserialize, Serialize};
+use std::env;
+use log::warn;
+use crate::errors::AppError;
+use crate::utils::jwt::{check_subject, introspect_token};
+use crate::utils::password::secrets_match;
+
+#[derive(Deserialize)]
+pub struct IntrospectRequest {
+    token: String,
+}
+
+// RFC 7662 response; an inactive token reveals nothing beyond `active: false`
+#[derive(Serialize, Default)]
+pub struct IntrospectResponse {
+    active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exp: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    aud: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+}
+
+// Realm named in the Basic challenge sent to callers without valid credentials
+const INTROSPECTION_REALM: &str = "introspection";
+
+// Accepts Basic credentials whose password is the introspection secret; the username is ignored
+fn authorize(credentials: Option<&BasicAuth>, secret: &str) -> Result<(), AppError> {
+    let authorized = credentials
+        .and_then(|credentials| credentials.password())
+        .is_some_and(|password| secrets_match(secret, password));
+    if !authorized {
+        warn!("Rejected token introspection with invalid credentials");
+        return Err(AppError::BasicUnauthorized("Invalid introspection credentials".to_string(), INTROSPECTION_REALM));
+    }
+    Ok(())
+}
+
+// POST /v1/token/introspect
+// For internal services, authenticated with HTTP Basic using INTROSPECTION_SECRET as the password.
+pub async fn introspect(
+    credentials: Option<BasicAuth>,
+    payload: web::Json<IntrospectRequest>,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let secret = env::var("INTROSPECTION_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty())
+        .ok_or_else(|| AppError::NotFound("Token introspection is not enabled".to_string()))?;
+
+    authorize(credentials.as_ref(), &secret)?;
+
+    let jwt_secret = env::var("JWT_SECRET")
+        .map_err(|_| AppError::InternalServerError("JWT secret not configured".to_string()))?;
+
+    let claims = match introspect_token(&payload.token, &jwt_secret).await {
+        Some(claims) => match check_subject(&pool, &claims.sub, claims.iat).await {
+            Ok(()) => Some(claims),
+            Err(AppError::Unauthorized(_)) => None,
+            Err(err) => return Err(err),
+        },
+        None => None,
+    };
+
+    let response = match claims {
+        Some(claims) => IntrospectResponse {
+            active: true,
+            sub: Some(claims.sub),
+            exp: Some(claims.exp),
+            iss: claims.iss,
+            aud: claims.aud,
+            scope: Some(claims.scope),
+        },
+        None => IntrospectResponse::default(),
+    };
+
+    // Return response
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use actix_web::{http::header, FromRequest, ResponseError};
+
+    async fn basic_auth(authorization: Option<&str>) -> Option<BasicAuth> {
+        let mut req = TestRequest::post();
+        if let Some(authorization) = authorization {
+            req = req.insert_header((header::AUTHORIZATION, authorization));
+        }
+        let (req, mut payload) = req.to_http_parts();
+        BasicAuth::from_request(&req, &mut payload).await.ok()
+    }
+
+    #[tokio::test]
+    async fn accepts_the_secret_as_password() {
+        // "svc:s3cret"
+        let credentials = basic_auth(Some("Basic c3ZjOnMzY3JldA==")).await;
+        assert!(authorize(credentials.as_ref(), "s3cret").is_ok());
+    }
+
+    #[tokio::test]
+    async fn failed_auth_challenges_for_basic_credentials() {
+        // "svc:wrong", then no credentials at all
+        for credentials in [basic_auth(Some("Basic c3ZjOndyb25n")).await, basic_auth(None).await] {
+            let response = authorize(credentials.as_ref(), "s3cret").unwrap_err().error_response();
+            assert_eq!(response.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+            assert_eq!(response.headers().get(header::WWW_AUTHENTICATE).unwrap(), "Basic realm=\"introspection\"");
+        }
+    }
+}
@@ -1,4 +1,8 @@
 pub mod auth;
 pub mod profile;
 pub mod file;
-pub mod activity;
\ No newline at end of file
+pub mod activity;
+pub mod analytics;
+pub mod admin;
+pub mod health;
+pub mod introspection;
\ No newline at end of file
@@ -1,11 +1,15 @@
 use actix_web::{web, HttpRequest, HttpResponse, HttpMessage};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
-use chrono::Utc;
-use crate::models::user::{GetUserProfile, GetUserId};
+use crate::models::user::{GetUserProfile, GetUserMeasurements};
 use crate::errors::AppError;
 use crate::utils::validation::{validate_preference, validate_weight_unit, validate_height_unit, validate_url};
 use crate::utils::jwt::Claims;
+use crate::utils::units::{convert_weight, convert_height, serialize_measurement};
+use crate::utils::timezone::{parse_timezone, resolve_timezone};
+use crate::db::timed;
+use chrono::{Datelike, Duration};
+use std::env;
 
 #[derive(Deserialize, Validate, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -13,9 +17,11 @@ pub struct ProfileUpdate {
     #[validate(length(min = 2, max = 60, message = "Name must be between 2 and 60 characters"))]
     name: Option<String>,
 
-    #[validate(url(message = "Invalid image URI"))]
+    // Checked by validate_url in update_profile, not the derive, so a bad URI yields one error
     image_uri: Option<String>,
 
+    // `range` on an Option only fires for Some, so omitting weight/height never trips these;
+    // presence is enforced separately by has_null_fields
     #[validate(range(min = 10, max = 1000, message = "Weight must be between 10 and 1000"))]
     weight: Option<f64>,
 
@@ -30,6 +36,13 @@ pub struct ProfileUpdate {
 
     #[validate(required(message = "Height unit is required"))]
     height_unit: Option<String>,
+
+    // Optional, kept as-is when omitted
+    timezone: Option<String>,
+
+    // Optional, kept as-is when omitted
+    #[validate(range(min = 1, max = 1000000, message = "Monthly calorie goal must be between 1 and 1000000"))]
+    monthly_calorie_goal: Option<i32>,
 }
 
 #[derive(Serialize)]
@@ -38,45 +51,128 @@ struct ProfileResponse {
     preference: Option<String>,
     weight_unit: Option<String>,
     height_unit: Option<String>,
+    #[serde(serialize_with = "serialize_measurement")]
     weight: Option<f64>,
+    #[serde(serialize_with = "serialize_measurement")]
     height: Option<f64>,
     email: String,
     name: Option<String>,
     image_uri: Option<String>,
+    last_login_at: Option<String>,
+    lifetime_calories: i64,
+    timezone: Option<String>,
+    monthly_calorie_goal: Option<i32>,
+}
+
+// Units assumed for a stored weight/height whose unit column is empty
+const CANONICAL_WEIGHT_UNIT: &str = "KG";
+const CANONICAL_HEIGHT_UNIT: &str = "CM";
+
+#[derive(Deserialize)]
+pub struct ProfileQuery {
+    weight_unit: Option<String>,
+    height_unit: Option<String>,
+    // Comma-separated sparse fieldset, e.g. "name,weight,height"
+    fields: Option<String>,
+}
+
+// Names accepted by `?fields=`, matching the camelCase keys of ProfileResponse
+const PROFILE_FIELDS: &[&str] = &[
+    "preference", "weightUnit", "heightUnit", "weight", "height", "email", "name", "imageUri",
+    "lastLoginAt", "lifetimeCalories", "timezone", "monthlyCalorieGoal",
+];
+
+// Parses `?fields=`, rejecting unknown names; email is always included and repeats are dropped
+fn parse_profile_fields(fields: &str) -> Result<Vec<String>, AppError> {
+    let mut requested = vec!["email".to_string()];
+    for field in fields.split(',').map(str::trim).filter(|field| !field.is_empty()) {
+        if !PROFILE_FIELDS.contains(&field) {
+            return Err(AppError::BadRequest(format!("Unknown profile field: {}", field)));
+        }
+        if !requested.iter().any(|seen| seen == field) {
+            requested.push(field.to_string());
+        }
+    }
+    Ok(requested)
 }
 
 // GET /v1/user
 pub async fn get_profile(
     req: HttpRequest,
     pool: web::Data<sqlx::PgPool>,
+    query: web::Query<ProfileQuery>,
 ) -> Result<HttpResponse, AppError> {
+    // Validate requested units
+    if let Some(weight_unit) = &query.weight_unit {
+        validate_weight_unit(weight_unit)?;
+    }
+    if let Some(height_unit) = &query.height_unit {
+        validate_height_unit(height_unit)?;
+    }
+    let fields = match &query.fields {
+        Some(fields) => Some(parse_profile_fields(fields)?),
+        None => None,
+    };
+
     // Extract claims from request extensions
-    let extensions = req.extensions();
-    let claims = extensions.get::<Claims>()
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
         .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
 
     // Fetch user from database
     let user = sqlx::query_as!(
         GetUserProfile,
-        "SELECT preference, weight_unit, height_unit, weight, height, name, image_uri FROM users WHERE email = $1",
-        claims.sub
+        "SELECT user_id, preference, weight_unit, height_unit, weight, height, name, image_uri, last_login_at, lifetime_calories, timezone, monthly_calorie_goal FROM users WHERE email = $1",
+        email
     )
     .fetch_optional(&**pool)
     .await
     .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
     .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
 
-    // Return response
-    Ok(HttpResponse::Ok().json(ProfileResponse {
+    // Convert stored values to the requested units, falling back to the saved units.
+    // Values saved without a unit are canonical metric.
+    let (weight_unit, weight) = match &query.weight_unit {
+        Some(to) => {
+            let from = user.weight_unit.as_deref().unwrap_or(CANONICAL_WEIGHT_UNIT);
+            (Some(to.clone()), user.weight.map(|w| convert_weight(w, from, to)))
+        }
+        None => (user.weight_unit, user.weight),
+    };
+    let (height_unit, height) = match &query.height_unit {
+        Some(to) => {
+            let from = user.height_unit.as_deref().unwrap_or(CANONICAL_HEIGHT_UNIT);
+            (Some(to.clone()), user.height.map(|h| convert_height(h, from, to)))
+        }
+        None => (user.height_unit, user.height),
+    };
+
+    let profile = ProfileResponse {
         preference: user.preference,
-        weight_unit: user.weight_unit,
-        height_unit: user.height_unit,
-        weight: user.weight,
-        height: user.height,
-        email: claims.sub.clone(),
+        weight_unit,
+        height_unit,
+        weight,
+        height,
+        email: email.clone(),
         name: user.name,
         image_uri: user.image_uri,
-    }))
+        last_login_at: user.last_login_at.map(|at| at.to_rfc3339()),
+        lifetime_calories: user.lifetime_calories,
+        timezone: user.timezone,
+        monthly_calorie_goal: user.monthly_calorie_goal,
+    };
+
+    // Return response, dropping unrequested keys entirely when a fieldset was given
+    let Some(fields) = fields else {
+        return Ok(HttpResponse::Ok().json(profile));
+    };
+    let mut body = serde_json::to_value(&profile)
+        .map_err(|_| AppError::InternalServerError("Serialization error".to_string()))?;
+    if let Some(object) = body.as_object_mut() {
+        object.retain(|key, _| fields.contains(key));
+    }
+    Ok(HttpResponse::Ok().json(body))
 }
 
 // Helper function to check for null values in the input
@@ -86,19 +182,49 @@ fn has_null_fields(updates: &ProfileUpdate) -> bool {
     updates.height_unit.is_none()
 }
 
+#[derive(Deserialize)]
+pub struct UpdateProfileQuery {
+    // Skips the plausibility check for a genuine large change
+    force: Option<bool>,
+}
+
+// Largest weight/height change accepted in one update, as a percentage of the stored value.
+// Unset (the default) disables the check.
+pub fn max_measurement_change_percent() -> Option<f64> {
+    env::var("MAX_MEASUREMENT_CHANGE_PERCENT")
+        .ok()
+        .and_then(|value| value.parse::<f64>().ok())
+        .filter(|value| *value > 0.0)
+}
+
+// Rejects a change of more than `max_percent` from the stored value, compared in the stored unit
+// so switching units alone never trips it
+fn check_plausible_change(field: &str, stored: Option<f64>, new: f64, max_percent: f64) -> Result<(), AppError> {
+    let Some(stored) = stored.filter(|stored| *stored > 0.0) else {
+        return Ok(());
+    };
+    let change = (new - stored).abs() / stored * 100.0;
+    if change > max_percent {
+        return Err(AppError::BadRequest(format!(
+            "{} changed by {:.0}%, check the unit or resend with force=true", field, change
+        )));
+    }
+    Ok(())
+}
+
 // PATCH /v1/user
 pub async fn update_profile(
     req: HttpRequest,
     pool: web::Data<sqlx::PgPool>,
+    query: web::Query<UpdateProfileQuery>,
     updates: web::Json<ProfileUpdate>,
 ) -> Result<HttpResponse, AppError> {
     // Extract claims from request extensions
-    let extensions = req.extensions();
-    let claims = extensions.get::<Claims>()
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
         .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
 
-    // Validate payload
-
     // Check for null values in the input
     if has_null_fields(&updates) {
         return Err(AppError::BadRequest("Fields cannot be null if provided".to_string()));
@@ -116,30 +242,50 @@ pub async fn update_profile(
     }
 
     if let Some(uri) = &updates.image_uri {
-        if uri.is_empty() {
-            return Err(AppError::BadRequest("Image URI cannot be empty if provided".to_string()));
-        }
-        validate_url(uri)?;
+        validate_url("imageUri", uri)?;
     }
 
-    // Validate the entire payload, including the image URI
+    // Normalize the timezone to its canonical form
+    let timezone = match &updates.timezone {
+        Some(timezone) => Some(parse_timezone(timezone)?.canonical()),
+        None => None,
+    };
+
+    // Validate the remaining payload fields
     updates.validate().map_err(|err| AppError::BadRequest(err.to_string()))?;
 
     // Fetch user from database
     let user = sqlx::query_as!(
-        GetUserId,
-        "SELECT user_id FROM users WHERE email = $1",
-        claims.sub
+        GetUserMeasurements,
+        "SELECT user_id, weight, height, weight_unit, height_unit FROM users WHERE email = $1",
+        email
     )
     .fetch_optional(&**pool)
     .await
     .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
     .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
 
+    // A jump like 70 -> 700 is almost always a unit mistake
+    if let (Some(max_percent), false) = (max_measurement_change_percent(), query.force.unwrap_or(false)) {
+        // has_null_fields guarantees every value and unit below is present
+        let weight = convert_weight(
+            updates.weight.unwrap(),
+            updates.weight_unit.as_deref().unwrap(),
+            user.weight_unit.as_deref().unwrap_or(updates.weight_unit.as_deref().unwrap()),
+        );
+        check_plausible_change("Weight", user.weight, weight, max_percent)?;
+
+        let height = convert_height(
+            updates.height.unwrap(),
+            updates.height_unit.as_deref().unwrap(),
+            user.height_unit.as_deref().unwrap_or(updates.height_unit.as_deref().unwrap()),
+        );
+        check_plausible_change("Height", user.height, height, max_percent)?;
+    }
+
     // Update user profile
-    let now = Utc::now();
-    sqlx::query!(
-        "UPDATE users SET preference = $1, weight_unit = $2, height_unit = $3, weight = $4, height = $5, name = $6, image_uri = $7, updated_at = $8 WHERE user_id = $9",
+    let updated = sqlx::query!(
+        "UPDATE users SET preference = $1, weight_unit = $2, height_unit = $3, weight = $4, height = $5, name = $6, image_uri = $7, timezone = COALESCE($9, timezone), monthly_calorie_goal = COALESCE($10, monthly_calorie_goal) WHERE user_id = $8 RETURNING last_login_at, lifetime_calories, timezone, monthly_calorie_goal",
         updates.preference,
         updates.weight_unit,
         updates.height_unit,
@@ -147,10 +293,11 @@ pub async fn update_profile(
         updates.height,
         updates.name,
         updates.image_uri,
-        now,
-        user.user_id
+        user.user_id,
+        timezone,
+        updates.monthly_calorie_goal
     )
-    .execute(&**pool)
+    .fetch_one(&**pool)
     .await
     .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
 
@@ -161,8 +308,279 @@ pub async fn update_profile(
         height_unit: updates.height_unit.clone(),
         weight: updates.weight,
         height: updates.height,
-        email: claims.sub.clone(),
+        email: email.clone(),
         name: updates.name.clone(),
         image_uri: updates.image_uri.clone(),
+        last_login_at: updated.last_login_at.map(|at| at.to_rfc3339()),
+        lifetime_calories: updated.lifetime_calories,
+        timezone: updated.timezone,
+        monthly_calorie_goal: updated.monthly_calorie_goal,
     }))
-}
\ No newline at end of file
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserStatsResponse {
+    profile: ProfileResponse,
+    totals: StatsTotals,
+    streak: StatsStreak,
+    goal: StatsGoal,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatsTotals {
+    activities: i64,
+    // Same counter as lifetimeCalories on GET /v1/user
+    calories: i64,
+    minutes: i64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatsStreak {
+    // Consecutive local days with an activity, ending today or yesterday
+    current_days: i64,
+    last_active_on: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StatsGoal {
+    monthly_calorie_goal: Option<i32>,
+    calories_this_month: i64,
+    // None without a goal; can exceed 100
+    progress_percent: Option<f64>,
+}
+
+// GET /v1/user/stats
+// Home screen summary in three queries: the profile, lifetime and month totals, and the latest
+// run of consecutive active days. Dates follow the profile timezone.
+pub async fn get_user_stats(
+    req: HttpRequest,
+    pool: web::Data<sqlx::PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let email = req.extensions()
+        .get::<Claims>()
+        .map(|claims| claims.sub.clone())
+        .ok_or_else(|| AppError::Unauthorized("Invalid token in claim".to_string()))?;
+
+    // Fetch user from database
+    let user = sqlx::query_as!(
+        GetUserProfile,
+        "SELECT user_id, preference, weight_unit, height_unit, weight, height, name, image_uri, last_login_at, lifetime_calories, timezone, monthly_calorie_goal FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(&**pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let timezone = resolve_timezone(None, user.timezone.as_deref())?;
+    let today = timezone.today();
+    let (month_from, _) = timezone.day_bounds_utc(today.with_day(1).unwrap_or(today));
+
+    let totals = timed("activities.user_stats", sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS "activities!",
+            COALESCE(SUM(duration_in_minutes), 0)::BIGINT AS "minutes!",
+            COALESCE(SUM(calories_burned) FILTER (WHERE done_at >= $2), 0)::BIGINT AS "month_calories!"
+        FROM activities
+        WHERE user_id = $1
+        "#,
+        user.user_id,
+        month_from
+    )
+    .fetch_one(&**pool))
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    // Gaps and islands: consecutive days share day - row_number, so the newest island is the
+    // latest streak. Future-dated activities are left out.
+    let latest_run = timed("activities.user_streak", sqlx::query!(
+        r#"
+        WITH days AS (
+            SELECT DISTINCT (done_at AT TIME ZONE $2)::date AS day
+            FROM activities
+            WHERE user_id = $1
+        ),
+        islands AS (
+            SELECT day, day - (ROW_NUMBER() OVER (ORDER BY day))::int AS island
+            FROM days
+            WHERE day <= $3
+        )
+        SELECT MAX(day) AS "last_day!", COUNT(*) AS "length!"
+        FROM islands
+        GROUP BY island
+        ORDER BY MAX(day) DESC
+        LIMIT 1
+        "#,
+        user.user_id,
+        timezone.pg_name(),
+        today
+    )
+    .fetch_optional(&**pool))
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?;
+
+    let streak = StatsStreak {
+        current_days: latest_run.as_ref()
+            .filter(|run| run.last_day >= today - Duration::days(1))
+            .map(|run| run.length)
+            .unwrap_or(0),
+        last_active_on: latest_run.as_ref().map(|run| run.last_day.to_string()),
+    };
+
+    let goal = StatsGoal {
+        monthly_calorie_goal: user.monthly_calorie_goal,
+        calories_this_month: totals.month_calories,
+        progress_percent: user.monthly_calorie_goal
+            .map(|goal| (totals.month_calories as f64 / goal as f64 * 10000.0).round() / 100.0),
+    };
+
+    // Return response
+    Ok(HttpResponse::Ok().json(UserStatsResponse {
+        totals: StatsTotals {
+            activities: totals.activities,
+            calories: user.lifetime_calories,
+            minutes: totals.minutes,
+        },
+        streak,
+        goal,
+        profile: ProfileResponse {
+            preference: user.preference,
+            weight_unit: user.weight_unit,
+            height_unit: user.height_unit,
+            weight: user.weight,
+            height: user.height,
+            email,
+            name: user.name,
+            image_uri: user.image_uri,
+            last_login_at: user.last_login_at.map(|at| at.to_rfc3339()),
+            lifetime_calories: user.lifetime_calories,
+            timezone: user.timezone,
+            monthly_calorie_goal: user.monthly_calorie_goal,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{authenticated_request, create_user, delete_user, response_json, test_pool};
+    use serde_json::json;
+
+    fn profile_update(extra: serde_json::Value) -> ProfileUpdate {
+        let mut body = json!({ "preference": "CARDIO", "weightUnit": "KG", "heightUnit": "CM" });
+        body.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn omitted_weight_and_height_skip_their_range_checks() {
+        assert!(profile_update(json!({})).validate().is_ok());
+        assert!(profile_update(json!({ "weight": 70.0 })).validate().is_ok());
+        assert!(profile_update(json!({ "height": 180.0 })).validate().is_ok());
+    }
+
+    #[test]
+    fn out_of_range_weight_and_height_are_rejected() {
+        let errors = profile_update(json!({ "weight": 5.0, "height": 180.0 })).validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("weight"));
+        assert!(!errors.field_errors().contains_key("height"));
+
+        let errors = profile_update(json!({ "height": 251.0 })).validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("height"));
+        assert!(!errors.field_errors().contains_key("weight"));
+    }
+
+    #[test]
+    fn changes_within_the_threshold_are_accepted() {
+        assert!(check_plausible_change("Weight", Some(80.0), 88.0, 10.0).is_ok());
+        assert!(check_plausible_change("Weight", Some(80.0), 72.0, 10.0).is_ok());
+        assert!(check_plausible_change("Height", Some(180.0), 180.0, 10.0).is_ok());
+    }
+
+    #[test]
+    fn changes_over_the_threshold_are_rejected() {
+        let err = check_plausible_change("Weight", Some(80.0), 176.0, 10.0).unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(msg) if msg == "Weight changed by 120%, check the unit or resend with force=true"));
+        assert!(check_plausible_change("Height", Some(180.0), 150.0, 10.0).is_err());
+    }
+
+    #[test]
+    fn first_value_is_never_implausible() {
+        assert!(check_plausible_change("Weight", None, 500.0, 10.0).is_ok());
+        assert!(check_plausible_change("Weight", Some(0.0), 500.0, 10.0).is_ok());
+    }
+
+    #[test]
+    fn unknown_profile_fields_are_rejected() {
+        let err = parse_profile_fields("name,password").unwrap_err();
+        assert!(matches!(err, AppError::BadRequest(msg) if msg == "Unknown profile field: password"));
+        // Names are the camelCase response keys
+        assert!(parse_profile_fields("image_uri").is_err());
+    }
+
+    #[test]
+    fn empty_fieldset_keeps_only_email() {
+        assert_eq!(parse_profile_fields("").unwrap(), ["email"]);
+        assert_eq!(parse_profile_fields(" , ,").unwrap(), ["email"]);
+    }
+
+    #[test]
+    fn repeated_profile_fields_are_listed_once() {
+        assert_eq!(parse_profile_fields("name, weight,name,email").unwrap(), ["email", "name", "weight"]);
+    }
+
+    async fn read_profile(pool: &sqlx::PgPool, email: &str, query: &str) -> serde_json::Value {
+        let query = web::Query::<ProfileQuery>::from_query(query).unwrap();
+        let response = get_profile(authenticated_request(email), web::Data::new(pool.clone()), query).await.unwrap();
+        response_json(response).await
+    }
+
+    async fn store_measurements(pool: &sqlx::PgPool, user_id: uuid::Uuid, units: Option<(&str, &str)>) {
+        sqlx::query("UPDATE users SET weight = 70, height = 180, weight_unit = $2, height_unit = $3 WHERE user_id = $1")
+            .bind(user_id)
+            .bind(units.map(|(weight, _)| weight))
+            .bind(units.map(|(_, height)| height))
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn kg_stored_profile_is_read_in_requested_units() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, email) = create_user(&pool).await;
+        store_measurements(&pool, user_id, Some(("KG", "CM"))).await;
+
+        let body = read_profile(&pool, &email, "weight_unit=LBS&height_unit=INCH").await;
+        assert_eq!(body["weightUnit"], "LBS");
+        assert_eq!(body["weight"], 154.32);
+        assert_eq!(body["heightUnit"], "INCH");
+        assert_eq!(body["height"], 70.87);
+
+        let body = read_profile(&pool, &email, "").await;
+        assert_eq!(body["weightUnit"], "KG");
+        assert_eq!(body["weight"], 70.0);
+
+        delete_user(&pool, user_id).await;
+    }
+
+    #[tokio::test]
+    async fn values_without_a_stored_unit_are_converted_from_metric() {
+        let Some(pool) = test_pool().await else { return };
+        let (user_id, email) = create_user(&pool).await;
+        store_measurements(&pool, user_id, None).await;
+
+        let body = read_profile(&pool, &email, "weight_unit=LBS&height_unit=INCH").await;
+        assert_eq!(body["weightUnit"], "LBS");
+        assert_eq!(body["weight"], 154.32);
+        assert_eq!(body["heightUnit"], "INCH");
+        assert_eq!(body["height"], 70.87);
+
+        delete_user(&pool, user_id).await;
+    }
+}
@@ -3,15 +3,19 @@ mod models;
 mod utils;
 mod db;
 mod errors;
+#[cfg(test)]
+mod test_support;
 
-use actix_web::{web, App, HttpServer};
+use actix_web::{web, App, HttpMessage, HttpServer};
 use actix_web_prom::PrometheusMetricsBuilder;
 use dotenv::dotenv;
 use std::env;
 use log::info;
 use crate::utils::s3::create_s3_client;
 use env_logger::Env;
-use actix_web::middleware::Logger;
+use actix_web::middleware::{Compress, Logger};
+use actix_web::dev::Service;
+use futures_util::future::{self, Either};
 use actix_web_httpauth::middleware::HttpAuthentication;
 use std::collections::HashMap;
 
@@ -29,22 +33,60 @@ async fn main() -> std::io::Result<()> {
         panic!("JWT_SECRET cannot be empty");
     }
 
+    // Validate auth cookie attributes up front when cookie auth is enabled
+    if crate::utils::cookie::cookie_auth_enabled() {
+        if let Err(err) = crate::utils::cookie::AuthCookieConfig::from_env() {
+            panic!("Invalid auth cookie configuration: {}", err);
+        }
+    }
+
+    // Load the common-password denylist and warm the dummy login hash
+    crate::utils::password::init_common_passwords();
+    crate::handlers::auth::init_dummy_password_hash();
+
+    // Load the activity type registry
+    crate::utils::activity_types::init_activity_types();
+
     // Initialize the database pool
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let pool = sqlx::postgres::PgPoolOptions::new()
+    let connect_attempts = env::var("DB_CONNECT_ATTEMPTS")
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .unwrap_or(5);
+    let connect_backoff = std::time::Duration::from_millis(
+        env::var("DB_CONNECT_BACKOFF_MS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(1000),
+    );
+    let pool = crate::db::connect_with_retry(connect_attempts, connect_backoff, || {
+        info!("Connecting to the database");
+        sqlx::postgres::PgPoolOptions::new()
             .max_connections(90)
             // .max_lifetime(std::time::Duration::from_secs(30))  // Recycle connections may increase throughput but also failure (upon further test it may also be just failure and less throughput)
             .idle_timeout(std::time::Duration::from_secs(10))
             .connect(&database_url)
-            .await
-            .expect("Failed to connect to the database");
+    })
+    .await
+    .expect("Failed to connect to the database");
+
+    // Background purge of old activities, only when ACTIVITY_RETENTION_DAYS is set
+    crate::db::retention::spawn_retention_job(pool.clone());
 
     // Fetch the server bind address from an environment variable, default to "127.0.0.1:8080"
     let bind_address = env::var("BIND_ADDRESS").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
     info!("Starting server at {}", bind_address);
 
+    // Slow clients trickling request heads are dropped after this, default matches actix (5s)
+    let client_request_timeout = std::time::Duration::from_secs(
+        env::var("CLIENT_REQUEST_TIMEOUT_SECONDS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(5),
+    );
+
     // Authentication middleware
-    let auth = HttpAuthentication::bearer(crate::utils::jwt::validator);
+    let auth = HttpAuthentication::with_fn(crate::utils::jwt::validator);
 
     // Set up Prometheus metrics
     let mut labels = HashMap::new();
@@ -54,14 +96,72 @@ async fn main() -> std::io::Result<()> {
         .const_labels(labels)
         .build()
         .expect("Failed to create Prometheus metrics");
+    crate::utils::metrics::register(&prometheus.registry);
+
+    // Only JSON/CSV/text bodies at or above this size get compressed
+    let compression_min_bytes = crate::utils::compression::compression_min_bytes();
+
+    // Header count/size limits, checked before routing
+    let header_limits = crate::utils::header_limits::HeaderLimits::from_env();
+
+    // Global in-flight request cap, shared across workers
+    let request_limiter = crate::utils::request_limiter::RequestLimiter::from_env();
 
     // Start the HTTP server
     HttpServer::new(move || {
+        let request_limiter = request_limiter.clone();
         App::new()
+            .wrap_fn(|req, srv| {
+                // Per-request query count, for spotting N+1 patterns
+                let counter = crate::db::QueryCounter::default();
+                req.extensions_mut().insert(counter.clone());
+                let fut = crate::db::with_query_counter(counter.clone(), srv.call(req));
+                async move {
+                    let res = fut.await?;
+                    if crate::db::log_query_count_enabled() {
+                        info!("{} {} ran {} queries", res.request().method(), res.request().path(), counter.count());
+                    }
+                    Ok(res)
+                }
+            })
+            .wrap_fn(move |req, srv| match request_limiter.try_admit(req.path()) {
+                Ok(permit) => {
+                    let fut = srv.call(req);
+                    Either::Left(async move {
+                        let res = fut.await;
+                        drop(permit);
+                        res
+                    })
+                }
+                Err(err) => Either::Right(future::ready(Err(err.into()))),
+            })
+            .wrap_fn(move |req, srv| match header_limits.check(req.headers()) {
+                Ok(()) => Either::Left(srv.call(req)),
+                Err(err) => Either::Right(future::ready(Err(err.into()))),
+            })
+            .wrap_fn(move |req, srv| {
+                // Response headers: compression opt-out and the per-route Cache-Control policy
+                let fut = srv.call(req);
+                async move {
+                    let mut res = fut.await?;
+                    crate::utils::compression::skip_compression(&mut res, compression_min_bytes);
+                    crate::utils::cache_control::apply_cache_control(&mut res);
+                    Ok(res)
+                }
+            })
+            .wrap(Compress::default()) // Response compression, see skip_compression
             .wrap(Logger::default()) // Logging middleware
             .wrap(prometheus.clone()) // Prometheus metrics middleware
             .app_data(web::Data::new(pool.clone())) // Database pool
             .app_data(web::Data::new(s3_client.clone())) // S3 client
+            .service(
+                web::resource("/ready")
+                    .route(web::get().to(handlers::health::ready)),
+            )
+            .service(
+                web::resource("/v1/version")
+                    .route(web::get().to(handlers::health::version)),
+            )
             .service(
                 web::resource("/v1/login")
                     .route(web::post().to(handlers::auth::login)),
@@ -70,32 +170,141 @@ async fn main() -> std::io::Result<()> {
                 web::resource("/v1/register")
                     .route(web::post().to(handlers::auth::register)),
             )
+            .service(
+                web::resource("/v1/verify-email")
+                    .route(web::post().to(handlers::auth::verify_email)),
+            )
+            .service(
+                web::resource("/v1/token/introspect")
+                    .route(web::post().to(handlers::introspection::introspect)),
+            )
             .service(
                 web::resource("/v1/user")
                     .wrap(auth.clone())
                     .route(web::get().to(handlers::profile::get_profile))
                     .route(web::patch().to(handlers::profile::update_profile)),
             )
+            .service(
+                web::resource("/v1/user/stats")
+                    .wrap(auth.clone())
+                    .route(web::get().to(handlers::profile::get_user_stats)),
+            )
+            .service(
+                web::resource("/v1/user/api-token")
+                    .wrap(auth.clone())
+                    .route(web::post().to(handlers::auth::issue_api_token)),
+            )
+            .service(
+                web::resource("/v1/user/email")
+                    .wrap(auth.clone())
+                    .route(web::post().to(handlers::auth::change_email)),
+            )
+            .service(
+                web::resource("/v1/user/email/confirm")
+                    .route(web::post().to(handlers::auth::confirm_email_change)),
+            )
+            .service(
+                web::resource("/v1/users")
+                    .wrap(auth.clone())
+                    .route(web::get().to(handlers::admin::list_users)),
+            )
+            .service(
+                web::resource("/v1/admin/config")
+                    .wrap(auth.clone())
+                    .route(web::get().to(handlers::admin::get_config)),
+            )
+            .service(
+                web::resource("/v1/user/activities/recent")
+                    .wrap(auth.clone())
+                    .route(web::get().to(handlers::activity::get_recent_activities)),
+            )
             .service(
                 web::resource("/v1/file")
                     .wrap(auth.clone())
-                    .route(web::post().to(handlers::file::upload_file)),
+                    .route(web::post().to(handlers::file::upload_file))
+                    .route(web::head().to(handlers::file::head_file)),
+            )
+            .service(
+                web::resource("/v1/file/token")
+                    .wrap(auth.clone())
+                    .route(web::get().to(handlers::file::issue_download_token)),
+            )
+            .service(
+                web::resource("/v1/file/download")
+                    .route(web::get().to(handlers::file::download_file)),
             )
             .service(
                 web::resource("/v1/activity")
                     .wrap(auth.clone())
+                    .app_data(web::QueryConfig::default().error_handler(
+                        crate::errors::query_error_handler::<handlers::activity::GetActivitiesQuery>,
+                    ))
+                    .app_data(web::JsonConfig::default().error_handler(crate::errors::json_error_handler))
                     .route(web::get().to(handlers::activity::get_activities))
-                    .route(web::post().to(handlers::activity::create_activity)),
+                    .route(web::post().to(handlers::activity::create_activity))
+                    .route(web::delete().to(handlers::activity::delete_all_activities)),
+            )
+            .service(
+                web::resource("/v1/activity/types")
+                    .route(web::get().to(handlers::activity::get_activity_types)),
+            )
+            .service(
+                web::resource("/v1/activity/weekly")
+                    .wrap(auth.clone())
+                    .route(web::get().to(handlers::analytics::get_weekly_aggregates)),
+            )
+            .service(
+                web::resource("/v1/activity/category-trend")
+                    .wrap(auth.clone())
+                    .route(web::get().to(handlers::analytics::get_category_trend)),
+            )
+            .service(
+                web::resource("/v1/activity/goal-trend")
+                    .wrap(auth.clone())
+                    .route(web::get().to(handlers::analytics::get_goal_trend)),
+            )
+            .service(
+                web::resource("/v1/activity/goal-eta")
+                    .wrap(auth.clone())
+                    .route(web::get().to(handlers::analytics::get_goal_eta)),
+            )
+            .service(
+                web::resource("/v1/activity/bulk")
+                    .wrap(auth.clone())
+                    .app_data(web::JsonConfig::default().error_handler(crate::errors::json_error_handler))
+                    .route(web::post().to(handlers::activity::bulk_create_activities)),
+            )
+            .service(
+                web::resource("/v1/activity/export")
+                    .wrap(auth.clone())
+                    .route(web::get().to(handlers::activity::export_activities)),
+            )
+            .service(
+                web::resource("/v1/activity/import")
+                    .wrap(auth.clone())
+                    .route(web::post().to(handlers::activity::import_activities)),
+            )
+            .service(
+                web::resource("/v1/activity/recompute")
+                    .wrap(auth.clone())
+                    .route(web::post().to(handlers::activity::recompute_activities)),
+            )
+            .service(
+                web::resource("/v1/activity/{activityId}/calorie-breakdown")
+                    .wrap(auth.clone())
+                    .route(web::get().to(handlers::activity::get_calorie_breakdown)),
             )
             .service(
                 web::resource("/v1/activity/{activityId}")
                     .wrap(auth.clone())
+                    .app_data(web::JsonConfig::default().error_handler(crate::errors::json_error_handler))
                     .route(web::patch().to(handlers::activity::update_activity))
                     .route(web::delete().to(handlers::activity::delete_activity)),
             )
     })
     .backlog(10_000)
     // .client_request_timeout(std::time::Duration::from_secs(2)) // May increase throughput but also failure (upon further test it may also be just failure and less throughput)
+    .client_request_timeout(client_request_timeout) // Time allowed to receive the request head, mitigates slowloris
     .bind(&bind_address)?
     .run()
     .await
@@ -9,12 +9,17 @@ pub struct Activity {
     pub user_id: Uuid,
     pub activity_type: String,
     pub done_at: chrono::DateTime<Utc>,
+    // Seconds east of UTC the client wrote done_at in; None for rows from before it was kept
+    pub done_at_offset: Option<i32>,
     pub duration_in_minutes: i32,
     pub calories_burned: i32,
+    pub calories_source: String,
     pub created_at: chrono::DateTime<Utc>,
     pub updated_at: chrono::DateTime<Utc>,
+    pub tags: Vec<String>,
 }
 
-pub struct GetActivityCreatedAt {
+pub struct GetActivityForUpdate {
     pub created_at: chrono::DateTime<Utc>,
+    pub calories_burned: i32,
 }
\ No newline at end of file
@@ -1,12 +1,15 @@
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 use uuid::Uuid;
 use chrono::Utc;
 
-#[derive(sqlx::FromRow, Serialize, Deserialize, Debug)]
-pub struct User {
-    pub user_id: Uuid,
-    pub email: String,
+pub struct GetUserLogin {
     pub password: String,
+    pub verified: bool,
+    pub role: String,
+}
+
+pub struct GetUserProfile {
+    pub user_id: Uuid,
     pub preference: Option<String>,
     pub weight_unit: Option<String>,
     pub height_unit: Option<String>,
@@ -14,24 +17,60 @@ pub struct User {
     pub height: Option<f64>,
     pub name: Option<String>,
     pub image_uri: Option<String>,
-    pub created_at: chrono::DateTime<Utc>,
-    pub updated_at: chrono::DateTime<Utc>,
+    pub last_login_at: Option<chrono::DateTime<Utc>>,
+    pub lifetime_calories: i64,
+    pub timezone: Option<String>,
+    pub monthly_calorie_goal: Option<i32>,
 }
 
-pub struct GetUserPassword {
-    pub password: String,
+pub struct GetUserId {
+    pub user_id: Uuid,
 }
 
-pub struct GetUserProfile {
+pub struct GetUserTimezone {
+    pub user_id: Uuid,
+    pub timezone: Option<String>,
+}
+
+pub struct GetUserActivityListState {
+    pub user_id: Uuid,
+    pub timezone: Option<String>,
+    pub last_modified: chrono::DateTime<Utc>,
+}
+
+pub struct GetUserCalorieGoal {
+    pub user_id: Uuid,
+    pub timezone: Option<String>,
+    pub monthly_calorie_goal: Option<i32>,
+}
+
+pub struct GetUserMeasurements {
+    pub user_id: Uuid,
+    pub weight: Option<f64>,
+    pub height: Option<f64>,
+    pub weight_unit: Option<String>,
+    pub height_unit: Option<String>,
+}
+
+pub struct GetUserWeight {
+    pub user_id: Uuid,
+    pub weight: Option<f64>,
+}
+// Admin listing view, never includes the password hash
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdminUserProfile {
+    pub user_id: Uuid,
+    pub email: String,
+    pub name: Option<String>,
     pub preference: Option<String>,
     pub weight_unit: Option<String>,
     pub height_unit: Option<String>,
     pub weight: Option<f64>,
     pub height: Option<f64>,
-    pub name: Option<String>,
     pub image_uri: Option<String>,
+    pub role: String,
+    pub verified: bool,
+    pub last_login_at: Option<chrono::DateTime<Utc>>,
+    pub created_at: chrono::DateTime<Utc>,
 }
-
-pub struct GetUserId {
-    pub user_id: Uuid,
-}
\ No newline at end of file
@@ -0,0 +1,74 @@
+//! Helpers for tests that need the database at `DATABASE_URL`. Without it, `test_pool` returns
+//! `None` and those tests return early.
+
+use actix_web::test::TestRequest;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::env;
+use uuid::Uuid;
+use crate::utils::jwt::{Claims, ROLE_USER, SCOPE_SESSION};
+
+pub async fn test_pool() -> Option<PgPool> {
+    let url = env::var("DATABASE_URL").ok()?;
+    Some(PgPool::connect(&url).await.expect("DATABASE_URL is set but unreachable"))
+}
+
+/// Inserts a user under a fresh address and returns its id and email.
+pub async fn create_user(pool: &PgPool) -> (Uuid, String) {
+    let user_id = Uuid::now_v7();
+    let email = format!("test-{}@example.com", user_id);
+    sqlx::query("INSERT INTO users (user_id, email, password, created_at, updated_at) VALUES ($1, $2, 'x', NOW(), NOW())")
+        .bind(user_id)
+        .bind(&email)
+        .execute(pool)
+        .await
+        .unwrap();
+    (user_id, email)
+}
+
+/// Inserts one formula-derived activity for `user_id`.
+pub async fn insert_activity(pool: &PgPool, user_id: Uuid, activity_type: &str, done_at: DateTime<Utc>, calories: i32) {
+    sqlx::query(
+        "INSERT INTO activities (activity_id, user_id, activity_type, done_at, duration_in_minutes, calories_burned, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, 30, $5, NOW(), NOW())",
+    )
+    .bind(Uuid::now_v7())
+    .bind(user_id)
+    .bind(activity_type)
+    .bind(done_at)
+    .bind(calories)
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// Removes a user created by `create_user` together with its activities.
+pub async fn delete_user(pool: &PgPool, user_id: Uuid) {
+    sqlx::query("DELETE FROM activities WHERE user_id = $1").bind(user_id).execute(pool).await.unwrap();
+    sqlx::query("DELETE FROM users WHERE user_id = $1").bind(user_id).execute(pool).await.unwrap();
+}
+
+/// A request carrying session claims for `email`, as the JWT validator would leave it.
+pub fn authenticated_request(email: &str) -> HttpRequest {
+    authenticated(TestRequest::default(), email)
+}
+
+/// `authenticated_request` for a request built up by the caller, e.g. with a query string.
+pub fn authenticated(req: TestRequest, email: &str) -> HttpRequest {
+    let req = req.to_http_request();
+    req.extensions_mut().insert(Claims {
+        sub: email.to_string(),
+        exp: usize::MAX,
+        scope: SCOPE_SESSION.to_string(),
+        role: ROLE_USER.to_string(),
+        iat: 0,
+    });
+    req
+}
+
+/// Reads a handler's JSON response body.
+pub async fn response_json(response: HttpResponse) -> serde_json::Value {
+    let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
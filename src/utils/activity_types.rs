@@ -0,0 +1,202 @@
+use lazy_static::lazy_static;
+use log::info;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+
+/// Server-side metadata for a supported activity type
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityTypeInfo {
+    pub name: String,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub icon: String,
+    pub category: String,
+    pub calories_per_minute: i32,
+    // Optional display MET; derived from caloriesPerMinute when absent
+    #[serde(default)]
+    pub met: Option<f64>,
+    // Optional sanity cap on calories for one activity of this type
+    #[serde(default)]
+    pub max_calories: Option<i32>,
+}
+
+// Body weight the flat per-minute multipliers are assumed to describe
+const REFERENCE_WEIGHT_KG: f64 = 70.0;
+
+impl ActivityTypeInfo {
+    fn builtin(name: &str, label: &str, icon: &str, category: &str, calories_per_minute: i32) -> Self {
+        ActivityTypeInfo {
+            name: name.to_string(),
+            label: label.to_string(),
+            icon: icon.to_string(),
+            category: category.to_string(),
+            calories_per_minute,
+            met: None,
+            max_calories: None,
+        }
+    }
+
+    /// MET value shown to clients. With flat multipliers this is approximated from
+    /// kcal/min = MET * 3.5 * kg / 200 at a 70 kg reference weight.
+    pub fn met(&self) -> f64 {
+        self.met.unwrap_or_else(|| {
+            let met = self.calories_per_minute as f64 * 200.0 / (3.5 * REFERENCE_WEIGHT_KG);
+            (met * 10.0).round() / 10.0
+        })
+    }
+}
+
+/// Built-in activity types, used when `ACTIVITY_TYPES_CONFIG` is unset
+fn default_activity_types() -> Vec<ActivityTypeInfo> {
+    vec![
+        ActivityTypeInfo::builtin("Walking", "Walking", "🚶", "CARDIO", 4),
+        ActivityTypeInfo::builtin("Yoga", "Yoga", "🧘", "FLEXIBILITY", 4),
+        ActivityTypeInfo::builtin("Stretching", "Stretching", "🤸", "FLEXIBILITY", 4),
+        ActivityTypeInfo::builtin("Cycling", "Cycling", "🚴", "CARDIO", 8),
+        ActivityTypeInfo::builtin("Swimming", "Swimming", "🏊", "CARDIO", 8),
+        ActivityTypeInfo::builtin("Dancing", "Dancing", "💃", "CARDIO", 8),
+        ActivityTypeInfo::builtin("Hiking", "Hiking", "🥾", "CARDIO", 10),
+        ActivityTypeInfo::builtin("Running", "Running", "🏃", "CARDIO", 10),
+        ActivityTypeInfo::builtin("HIIT", "HIIT", "🔥", "WEIGHT", 10),
+        ActivityTypeInfo::builtin("JumpRope", "Jump Rope", "🪢", "CARDIO", 10),
+    ]
+}
+
+lazy_static! {
+    /// Every supported activity type. An entry here is valid for logging and advertised on
+    /// `GET /v1/activity/types`.
+    pub static ref ACTIVITY_TYPES: Vec<ActivityTypeInfo> = load_activity_types();
+
+    /// Names other apps use for our types, keyed lower-case, applied by imports with
+    /// `unknownTypePolicy=map`
+    static ref ACTIVITY_TYPE_ALIASES: HashMap<String, &'static str> = load_activity_type_aliases();
+}
+
+// Reads `ACTIVITY_TYPE_ALIASES` as comma-separated `alias=Type` pairs, e.g. `Run=Running,Bike=Cycling`.
+// Like the type config, an alias to an unknown type panics at startup.
+fn load_activity_type_aliases() -> HashMap<String, &'static str> {
+    let value = env::var("ACTIVITY_TYPE_ALIASES").unwrap_or_default();
+    let mut aliases = HashMap::new();
+    for pair in value.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let (alias, target) = pair.split_once('=')
+            .unwrap_or_else(|| panic!("ACTIVITY_TYPE_ALIASES entry {} must be alias=Type", pair));
+        let target = find_activity_type(target.trim())
+            .unwrap_or_else(|| panic!("ACTIVITY_TYPE_ALIASES maps {} to unknown type {}", alias.trim(), target.trim()));
+        aliases.insert(alias.trim().to_lowercase(), target.name.as_str());
+    }
+    aliases
+}
+
+// Reads the JSON array at `ACTIVITY_TYPES_CONFIG` when set, otherwise the built-in list.
+// A broken config is a deployment error, so it panics instead of silently falling back.
+fn load_activity_types() -> Vec<ActivityTypeInfo> {
+    let path = match env::var("ACTIVITY_TYPES_CONFIG") {
+        Ok(path) if !path.is_empty() => path,
+        _ => return default_activity_types(),
+    };
+
+    let contents = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Failed to read ACTIVITY_TYPES_CONFIG {}: {}", path, e));
+    let mut types: Vec<ActivityTypeInfo> = serde_json::from_str(&contents)
+        .unwrap_or_else(|e| panic!("Invalid ACTIVITY_TYPES_CONFIG {}: {}", path, e));
+
+    if types.is_empty() {
+        panic!("ACTIVITY_TYPES_CONFIG {} defines no activity types", path);
+    }
+    for (index, info) in types.iter_mut().enumerate() {
+        if info.name.is_empty() || info.category.is_empty() {
+            panic!("ACTIVITY_TYPES_CONFIG entry {} needs a name and a category", index);
+        }
+        if info.calories_per_minute < 0 {
+            panic!("ACTIVITY_TYPES_CONFIG entry {} has negative caloriesPerMinute", info.name);
+        }
+        if info.met.is_some_and(|met| met <= 0.0) {
+            panic!("ACTIVITY_TYPES_CONFIG entry {} has a non-positive met", info.name);
+        }
+        if info.max_calories.is_some_and(|max| max <= 0) {
+            panic!("ACTIVITY_TYPES_CONFIG entry {} has a non-positive maxCalories", info.name);
+        }
+        if info.label.is_empty() {
+            info.label = info.name.clone();
+        }
+    }
+    let mut names: Vec<&str> = types.iter().map(|info| info.name.as_str()).collect();
+    names.sort_unstable();
+    if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+        panic!("ACTIVITY_TYPES_CONFIG defines {} more than once", pair[0]);
+    }
+
+    types
+}
+
+/// Forces the registry to load so a bad config is reported at startup rather than on first use.
+pub fn init_activity_types() {
+    info!("Loaded {} activity types and {} aliases", ACTIVITY_TYPES.len(), ACTIVITY_TYPE_ALIASES.len());
+}
+
+pub fn find_activity_type(name: &str) -> Option<&'static ActivityTypeInfo> {
+    ACTIVITY_TYPES.iter().find(|info| info.name == name)
+}
+
+/// Configured type an alias stands for, matched case-insensitively
+pub fn resolve_activity_alias(alias: &str) -> Option<&'static str> {
+    ACTIVITY_TYPE_ALIASES.get(&alias.trim().to_lowercase()).copied()
+}
+
+/// Returns the dashboard grouping (`CARDIO`, `WEIGHT`, `FLEXIBILITY`, or a custom one from the
+/// config) for an activity type, or `OTHER` for unknown types.
+pub fn activity_category(activity_type: &str) -> &'static str {
+    find_activity_type(activity_type)
+        .map(|info| info.category.as_str())
+        .unwrap_or("OTHER")
+}
+
+/// Names of every activity type in a category, e.g. the user's `CARDIO` or `WEIGHT` preference.
+pub fn activity_types_in_category(category: &str) -> Vec<String> {
+    ACTIVITY_TYPES
+        .iter()
+        .filter(|info| info.category == category)
+        .map(|info| info.name.clone())
+        .collect()
+}
+
+/// Returns the display MET for an activity type, or `None` for unknown types.
+pub fn activity_met(activity_type: &str) -> Option<f64> {
+    find_activity_type(activity_type).map(|info| info.met())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_builtin_type_has_its_category() {
+        let expected = [
+            ("Walking", "CARDIO"),
+            ("Yoga", "FLEXIBILITY"),
+            ("Stretching", "FLEXIBILITY"),
+            ("Cycling", "CARDIO"),
+            ("Swimming", "CARDIO"),
+            ("Dancing", "CARDIO"),
+            ("Hiking", "CARDIO"),
+            ("Running", "CARDIO"),
+            ("HIIT", "WEIGHT"),
+            ("JumpRope", "CARDIO"),
+        ];
+        assert_eq!(expected.len(), default_activity_types().len());
+        for (activity_type, category) in expected {
+            assert_eq!(activity_category(activity_type), category, "{activity_type}");
+        }
+    }
+
+    #[test]
+    fn unknown_types_fall_back_to_other() {
+        assert_eq!(activity_category("Skydiving"), "OTHER");
+        assert_eq!(activity_category("running"), "OTHER");
+        assert_eq!(activity_category(""), "OTHER");
+    }
+}
@@ -0,0 +1,36 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+
+const NO_STORE: &str = "no-store";
+const PRIVATE_LIST: &str = "private, max-age=10";
+const PRIVATE_SUMMARY: &str = "private, max-age=60";
+const PUBLIC_METADATA: &str = "public, max-age=3600";
+
+// Only successful reads are cacheable. Auth, profile, admin and every write or error is no-store.
+fn cache_policy(method: &Method, path: &str, success: bool) -> &'static str {
+    if !success || (method != Method::GET && method != Method::HEAD) {
+        return NO_STORE;
+    }
+
+    match path.trim_end_matches('/') {
+        "/v1/activity/types" => PUBLIC_METADATA,
+        "/v1/activity" | "/v1/user/activities/recent" => PRIVATE_LIST,
+        "/v1/activity/weekly" | "/v1/activity/category-trend" | "/v1/activity/goal-trend"
+        | "/v1/activity/goal-eta" | "/v1/user/stats" => PRIVATE_SUMMARY,
+        _ => NO_STORE,
+    }
+}
+
+/// Sets `Cache-Control` from the route policy unless the handler already chose one.
+pub fn apply_cache_control<B: MessageBody>(res: &mut ServiceResponse<B>) {
+    if res.headers().contains_key(header::CACHE_CONTROL) {
+        return;
+    }
+
+    // A 304 revalidates a cached 200, so it carries the same policy
+    let reusable = res.status().is_success() || res.status() == StatusCode::NOT_MODIFIED;
+    let policy = cache_policy(res.request().method(), res.request().path(), reusable);
+    res.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static(policy));
+}
@@ -0,0 +1,185 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+struct Inner {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probe_in_flight: bool,
+}
+
+/// Minimal closed/open/half-open circuit breaker.
+///
+/// After `failure_threshold` consecutive failures the breaker opens and rejects calls for
+/// `cooldown`. Once the cooldown passes a single probe is let through (half-open); its outcome
+/// closes the breaker again or reopens it for another cooldown. A probe whose permit is dropped
+/// without an outcome (client gone, early return) frees the slot for the next caller.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<Inner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            inner: Mutex::new(Inner {
+                state: BreakerState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                probe_in_flight: false,
+            }),
+        }
+    }
+
+    /// Returns a permit when a call may proceed right now. Report the call's outcome through it.
+    pub fn allow_request(&self) -> Option<BreakerPermit<'_>> {
+        let mut inner = self.inner.lock().unwrap();
+        let probe = match inner.state {
+            BreakerState::Closed => false,
+            BreakerState::Open => {
+                if inner.opened_at.is_none_or(|at| at.elapsed() < self.cooldown) {
+                    return None;
+                }
+                inner.state = BreakerState::HalfOpen;
+                true
+            }
+            BreakerState::HalfOpen if inner.probe_in_flight => return None,
+            BreakerState::HalfOpen => true,
+        };
+        if probe {
+            inner.probe_in_flight = true;
+        }
+        Some(BreakerPermit { breaker: self, probe, resolved: false })
+    }
+
+    fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.state = BreakerState::Closed;
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+        inner.probe_in_flight = false;
+    }
+
+    fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        inner.probe_in_flight = false;
+        if inner.state == BreakerState::HalfOpen || inner.consecutive_failures >= self.failure_threshold {
+            inner.state = BreakerState::Open;
+            inner.opened_at = Some(Instant::now());
+        }
+    }
+
+    // The probe ended without an outcome; stay half-open and let the next caller probe
+    fn abandon_probe(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == BreakerState::HalfOpen {
+            inner.probe_in_flight = false;
+        }
+    }
+}
+
+/// One admitted call. Consume it with `success` or `failure`; dropping it unreported only
+/// matters for a half-open probe, whose slot is released.
+pub struct BreakerPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    resolved: bool,
+}
+
+impl BreakerPermit<'_> {
+    pub fn success(mut self) {
+        self.resolved = true;
+        self.breaker.record_success();
+    }
+
+    pub fn failure(mut self) {
+        self.resolved = true;
+        self.breaker.record_failure();
+    }
+}
+
+impl Drop for BreakerPermit<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.resolved {
+            self.breaker.abandon_probe();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trip(breaker: &CircuitBreaker, failures: u32) {
+        for _ in 0..failures {
+            breaker.allow_request().expect("breaker should admit while closed").failure();
+        }
+    }
+
+    #[test]
+    fn opens_after_threshold_and_rejects_during_cooldown() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        trip(&breaker, 2);
+        assert!(breaker.allow_request().is_some());
+
+        trip(&breaker, 1);
+        assert!(breaker.allow_request().is_none());
+    }
+
+    #[test]
+    fn success_resets_the_failure_count() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+        trip(&breaker, 1);
+        breaker.allow_request().unwrap().success();
+        trip(&breaker, 1);
+        assert!(breaker.allow_request().is_some());
+    }
+
+    #[test]
+    fn half_open_admits_one_probe_and_closes_on_success() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        trip(&breaker, 1);
+
+        let probe = breaker.allow_request().expect("cooled down breaker should admit a probe");
+        assert!(breaker.allow_request().is_none());
+        probe.success();
+
+        let first = breaker.allow_request();
+        let second = breaker.allow_request();
+        assert!(first.is_some() && second.is_some());
+    }
+
+    #[test]
+    fn failed_probe_reopens() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(50));
+        trip(&breaker, 1);
+        std::thread::sleep(Duration::from_millis(60));
+
+        breaker.allow_request().expect("cooled down breaker should admit a probe").failure();
+        assert!(breaker.allow_request().is_none());
+    }
+
+    #[test]
+    fn abandoned_probe_frees_the_slot() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        trip(&breaker, 1);
+
+        let probe = breaker.allow_request().expect("cooled down breaker should admit a probe");
+        assert!(breaker.allow_request().is_none());
+        drop(probe);
+
+        breaker.allow_request().expect("abandoned probe should not wedge the breaker").success();
+        assert!(breaker.allow_request().is_some());
+    }
+}
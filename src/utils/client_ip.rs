@@ -0,0 +1,81 @@
+use actix_web::HttpRequest;
+use std::env;
+use std::net::IpAddr;
+
+// Number of proxies in front of the app that append to X-Forwarded-For (TRUSTED_PROXY_COUNT)
+fn trusted_proxy_count() -> usize {
+    env::var("TRUSTED_PROXY_COUNT")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(1)
+}
+
+/// Picks the client address from an `X-Forwarded-For` value appended to by `trusted_hops` proxies.
+///
+/// Each proxy appends the address it received the request from, so only the rightmost
+/// `trusted_hops` entries are trustworthy and the client is the leftmost of those. Entries further
+/// left were sent by the client and are ignored, which stops `X-Forwarded-For: 1.2.3.4` spoofing.
+/// A chain shorter than the hop count falls back to its first entry. Anything that is not an IP
+/// address yields `None`.
+fn forwarded_client_ip(header: &str, trusted_hops: usize) -> Option<String> {
+    let hops: Vec<&str> = header.split(',').map(str::trim).collect();
+    let index = hops.len().saturating_sub(trusted_hops);
+    hops.get(index)
+        .and_then(|hop| hop.parse::<IpAddr>().ok())
+        .map(|ip| ip.to_string())
+}
+
+/// Best-effort client IP. `X-Forwarded-For` is only honored when `TRUST_PROXY=true`, and then only
+/// as far back as `TRUSTED_PROXY_COUNT` hops (default 1). Otherwise the peer address is used so
+/// clients cannot spoof their IP.
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let trust_proxy = env::var("TRUST_PROXY").map(|v| v == "true").unwrap_or(false);
+
+    if trust_proxy {
+        let forwarded = req.headers()
+            .get("X-Forwarded-For")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| forwarded_client_ip(value, trusted_proxy_count()));
+        if forwarded.is_some() {
+            return forwarded;
+        }
+    }
+
+    req.peer_addr().map(|addr| addr.ip().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn spoofed_entries_left_of_the_trusted_hops_are_ignored() {
+        let chain = "6.6.6.6, 10.0.0.1, 203.0.113.7";
+        assert_eq!(forwarded_client_ip(chain, 1).as_deref(), Some("203.0.113.7"));
+        assert_eq!(forwarded_client_ip(chain, 2).as_deref(), Some("10.0.0.1"));
+        assert_eq!(forwarded_client_ip(chain, 3).as_deref(), Some("6.6.6.6"));
+    }
+
+    #[test]
+    fn short_chains_fall_back_to_their_first_entry() {
+        assert_eq!(forwarded_client_ip("203.0.113.7", 2).as_deref(), Some("203.0.113.7"));
+        assert_eq!(forwarded_client_ip(" 2001:db8::1 ", 1).as_deref(), Some("2001:db8::1"));
+    }
+
+    #[test]
+    fn non_ip_entries_yield_none() {
+        assert_eq!(forwarded_client_ip("1.2.3.4, unknown", 1), None);
+        assert_eq!(forwarded_client_ip("", 1), None);
+    }
+
+    #[test]
+    fn forwarded_header_is_ignored_unless_proxies_are_trusted() {
+        let req = TestRequest::default()
+            .peer_addr("192.0.2.10:4000".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "6.6.6.6"))
+            .to_http_request();
+        assert_eq!(client_ip(&req).as_deref(), Some("192.0.2.10"));
+    }
+}
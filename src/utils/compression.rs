@@ -0,0 +1,37 @@
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::ServiceResponse;
+use actix_web::http::header::{self, HeaderValue};
+use std::env;
+
+// Content types worth spending CPU on; images and other binary payloads are already compressed
+const COMPRESSIBLE_TYPES: &[&str] = &["application/json", "text/csv", "text/plain", "text/html"];
+
+/// Smallest body, in bytes, that the compression middleware will encode (`COMPRESSION_MIN_BYTES`).
+pub fn compression_min_bytes() -> u64 {
+    env::var("COMPRESSION_MIN_BYTES")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(1024)
+}
+
+fn is_compressible(content_type: Option<&HeaderValue>) -> bool {
+    content_type
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|mime| COMPRESSIBLE_TYPES.contains(&mime.trim().to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Opts a response out of `Compress` when it is below the threshold or not a compressible type.
+/// Must run inside `Compress`, which leaves responses that already carry `Content-Encoding` alone.
+pub fn skip_compression<B: MessageBody>(res: &mut ServiceResponse<B>, min_bytes: u64) {
+    let small = match res.response().body().size() {
+        BodySize::Sized(size) => size < min_bytes,
+        BodySize::None => true,
+        BodySize::Stream => false,
+    };
+
+    if small || !is_compressible(res.headers().get(header::CONTENT_TYPE)) {
+        res.headers_mut().insert(header::CONTENT_ENCODING, HeaderValue::from_static("identity"));
+    }
+}
@@ -0,0 +1,106 @@
+use actix_web::cookie::{time::Duration, Cookie, SameSite};
+use std::env;
+
+pub const AUTH_COOKIE_NAME: &str = "auth_token";
+
+/// Attributes applied to the auth cookie, read from `AUTH_COOKIE_*` env vars
+pub struct AuthCookieConfig {
+    pub domain: Option<String>,
+    pub same_site: SameSite,
+    pub secure: bool,
+}
+
+/// Cookie auth is opt-in via `AUTH_COOKIE_ENABLED=true`
+pub fn cookie_auth_enabled() -> bool {
+    env::var("AUTH_COOKIE_ENABLED").map(|v| v == "true").unwrap_or(false)
+}
+
+impl AuthCookieConfig {
+    /// Reads and validates the cookie attributes. `SameSite=None` is only accepted with `Secure`,
+    /// browsers reject the cookie otherwise.
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(
+            env::var("AUTH_COOKIE_DOMAIN").ok(),
+            env::var("AUTH_COOKIE_SAMESITE").ok(),
+            env::var("AUTH_COOKIE_SECURE").ok(),
+        )
+    }
+
+    fn parse(domain: Option<String>, same_site: Option<String>, secure: Option<String>) -> Result<Self, String> {
+        let domain = domain.filter(|d| !d.is_empty());
+
+        let same_site = match same_site.unwrap_or_else(|| "strict".to_string()).to_lowercase().as_str() {
+            "strict" => SameSite::Strict,
+            "lax" => SameSite::Lax,
+            "none" => SameSite::None,
+            other => return Err(format!("AUTH_COOKIE_SAMESITE must be strict, lax or none, got {}", other)),
+        };
+
+        let secure = match secure {
+            Some(value) => value.parse::<bool>().map_err(|_| "AUTH_COOKIE_SECURE must be true or false".to_string())?,
+            None => true,
+        };
+
+        if same_site == SameSite::None && !secure {
+            return Err("AUTH_COOKIE_SAMESITE=none requires AUTH_COOKIE_SECURE=true".to_string());
+        }
+
+        Ok(AuthCookieConfig { domain, same_site, secure })
+    }
+
+    /// Builds the HttpOnly auth cookie carrying the token, expiring with it
+    pub fn build(&self, token: String, max_age: chrono::Duration) -> Cookie<'static> {
+        let mut cookie = Cookie::build(AUTH_COOKIE_NAME, token)
+            .path("/")
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(self.same_site)
+            .max_age(Duration::seconds(max_age.num_seconds()))
+            .finish();
+
+        if let Some(domain) = &self.domain {
+            cookie.set_domain(domain.clone());
+        }
+        cookie
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(same_site: Option<&str>, secure: Option<&str>) -> Result<AuthCookieConfig, String> {
+        AuthCookieConfig::parse(None, same_site.map(str::to_string), secure.map(str::to_string))
+    }
+
+    #[test]
+    fn parses_each_same_site_value() {
+        assert_eq!(parse(None, None).unwrap().same_site, SameSite::Strict);
+        assert_eq!(parse(Some("strict"), None).unwrap().same_site, SameSite::Strict);
+        assert_eq!(parse(Some("Lax"), Some("false")).unwrap().same_site, SameSite::Lax);
+        assert_eq!(parse(Some("none"), Some("true")).unwrap().same_site, SameSite::None);
+        assert!(parse(Some("sometimes"), None).is_err());
+    }
+
+    #[test]
+    fn same_site_none_requires_secure() {
+        assert_eq!(
+            parse(Some("none"), Some("false")).err().unwrap(),
+            "AUTH_COOKIE_SAMESITE=none requires AUTH_COOKIE_SECURE=true"
+        );
+        assert!(parse(Some("none"), None).unwrap().secure);
+        assert!(parse(None, Some("yes")).is_err());
+    }
+
+    #[test]
+    fn builds_cookie_with_configured_attributes() {
+        let config = AuthCookieConfig::parse(Some("example.com".to_string()), Some("lax".to_string()), None).unwrap();
+        let cookie = config.build("token".to_string(), chrono::Duration::days(7));
+
+        assert_eq!(cookie.domain(), Some("example.com"));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.max_age(), Some(Duration::days(7)));
+    }
+}
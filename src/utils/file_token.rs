@@ -0,0 +1,89 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::env;
+use crate::errors::AppError;
+use crate::utils::password::secrets_match;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Key for download tokens (`FILE_TOKEN_SECRET`); the endpoints are disabled while it is unset
+pub fn file_token_secret() -> Option<String> {
+    env::var("FILE_TOKEN_SECRET").ok().filter(|secret| !secret.is_empty())
+}
+
+/// How long a download token stays valid (`FILE_TOKEN_TTL_SECONDS`, default 300)
+pub fn file_token_ttl() -> i64 {
+    env::var("FILE_TOKEN_TTL_SECONDS")
+        .ok()
+        .and_then(|value| value.parse::<i64>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(300)
+}
+
+// Hex HMAC-SHA256 over "<expires>.<key>"
+fn signature(key: &str, expires: i64, secret: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", expires, key).as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Builds "<expires>.<signature>.<key>". The key goes last since object keys contain dots.
+pub fn sign_file_token(key: &str, expires: i64, secret: &str) -> String {
+    format!("{}.{}.{}", expires, signature(key, expires, secret), key)
+}
+
+/// Returns the object key of a token whose signature matches and which has not expired at `now`
+pub fn verify_file_token(token: &str, secret: &str, now: i64) -> Result<String, AppError> {
+    let invalid = || AppError::Forbidden("Invalid or expired download token".to_string());
+
+    let mut parts = token.splitn(3, '.');
+    let (Some(expires), Some(given), Some(key)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    let expires = expires.parse::<i64>().map_err(|_| invalid())?;
+
+    if key.is_empty() || !secrets_match(&signature(key, expires, secret), given) {
+        return Err(invalid());
+    }
+    if expires <= now {
+        return Err(invalid());
+    }
+    Ok(key.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+    const KEY: &str = "0a1b2c.png";
+
+    #[test]
+    fn signed_token_verifies_until_it_expires() {
+        let token = sign_file_token(KEY, 1_000, SECRET);
+        assert_eq!(verify_file_token(&token, SECRET, 999).unwrap(), KEY);
+        assert!(verify_file_token(&token, SECRET, 1_000).is_err());
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let token = sign_file_token(KEY, 1_000, SECRET);
+        let other_key = token.replace(KEY, "ffffff.png");
+        let later_expiry = token.replacen("1000", "2000", 1);
+
+        assert!(verify_file_token(&other_key, SECRET, 0).is_err());
+        assert!(verify_file_token(&later_expiry, SECRET, 0).is_err());
+        assert!(verify_file_token(&token, "another-secret", 0).is_err());
+    }
+
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        for token in ["", "1000", "1000.abc", "soon.abc.key.png", "1000.abc."] {
+            assert!(verify_file_token(token, SECRET, 0).is_err(), "{token:?}");
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use actix_web::http::header::HeaderMap;
+use std::env;
+use crate::errors::AppError;
+
+// actix-http's parser already stops at 96 headers and a 128KiB head, so larger settings are capped
+const PARSER_MAX_HEADERS: usize = 96;
+const PARSER_MAX_HEAD_BYTES: usize = 131_072;
+
+/// Limits applied to every request's headers before routing
+#[derive(Clone, Copy)]
+pub struct HeaderLimits {
+    pub max_headers: usize,
+    pub max_header_bytes: usize,
+}
+
+impl HeaderLimits {
+    /// Reads `MAX_HEADERS` (default 64) and `MAX_HEADER_BYTES` (default 16384)
+    pub fn from_env() -> Self {
+        let max_headers = env::var("MAX_HEADERS")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(64)
+            .min(PARSER_MAX_HEADERS);
+        let max_header_bytes = env::var("MAX_HEADER_BYTES")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(16_384)
+            .min(PARSER_MAX_HEAD_BYTES);
+
+        HeaderLimits { max_headers, max_header_bytes }
+    }
+
+    /// Rejects requests with too many headers or too many header bytes (names plus values)
+    pub fn check(&self, headers: &HeaderMap) -> Result<(), AppError> {
+        if headers.len() > self.max_headers {
+            return Err(AppError::RequestHeaderFieldsTooLarge("Too many request headers".to_string()));
+        }
+
+        let header_bytes: usize = headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+        if header_bytes > self.max_header_bytes {
+            return Err(AppError::RequestHeaderFieldsTooLarge("Request headers too large".to_string()));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::env;
+use crate::errors::AppError;
+
+// Reads width and height from the PNG IHDR chunk, which always directly follows the signature
+fn png_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if data.len() < 24 || &data[12..16] != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+    let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]);
+    Some((width, height))
+}
+
+// Walks the JPEG marker segments up to the first start-of-frame, which carries the dimensions
+fn jpeg_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let mut index = 2;
+    while index + 9 < data.len() {
+        if data[index] != 0xFF {
+            return None;
+        }
+        let marker = data[index + 1];
+        match marker {
+            // Fill byte before a marker
+            0xFF => index += 1,
+            // Markers without a length field
+            0x01 | 0xD0..=0xD7 => index += 2,
+            // SOF0-SOF15, excluding DHT (C4), JPG (C8) and DAC (CC)
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                let height = u16::from_be_bytes([data[index + 5], data[index + 6]]);
+                let width = u16::from_be_bytes([data[index + 7], data[index + 8]]);
+                return Some((width as u32, height as u32));
+            }
+            _ => {
+                let length = u16::from_be_bytes([data[index + 2], data[index + 3]]) as usize;
+                index += 2 + length;
+            }
+        }
+    }
+    None
+}
+
+/// Pixel dimensions `(width, height)` of a PNG or JPEG image, or `None` if the header is unreadable
+pub fn image_dimensions(data: &[u8], mime_type: &str) -> Option<(u32, u32)> {
+    match mime_type {
+        "image/png" => png_dimensions(data),
+        "image/jpeg" | "image/jpg" => jpeg_dimensions(data),
+        _ => None,
+    }
+}
+
+/// Largest decoded size, in pixels, an uploaded image may declare (`MAX_IMAGE_PIXELS`)
+pub fn max_image_pixels() -> u64 {
+    env::var("MAX_IMAGE_PIXELS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(25_000_000)
+}
+
+/// Rejects images whose header declares more than `MAX_IMAGE_PIXELS` pixels. Small files can
+/// declare huge dimensions, so this runs on the header alone, before anything would decode them.
+pub fn validate_pixel_count(data: &[u8], mime_type: &str) -> Result<(), AppError> {
+    let (width, height) = image_dimensions(data, mime_type)
+        .ok_or_else(|| AppError::BadRequest("Unable to read image dimensions".to_string()))?;
+
+    let limit = max_image_pixels();
+    if width as u64 * height as u64 > limit {
+        return Err(AppError::BadRequest(format!(
+            "Image dimensions {}x{} exceed the limit of {} pixels",
+            width, height, limit
+        )));
+    }
+    Ok(())
+}
+
+fn ratio_bound(name: &str) -> Option<f64> {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<f64>().ok())
+        .filter(|value| value.is_finite() && *value > 0.0)
+}
+
+/// Rejects images whose width/height ratio is outside `AVATAR_MIN_RATIO`..`AVATAR_MAX_RATIO`.
+/// Either bound may be left unset; with neither set every ratio is accepted.
+pub fn validate_aspect_ratio(data: &[u8], mime_type: &str) -> Result<(), AppError> {
+    let min_ratio = ratio_bound("AVATAR_MIN_RATIO");
+    let max_ratio = ratio_bound("AVATAR_MAX_RATIO");
+    if min_ratio.is_none() && max_ratio.is_none() {
+        return Ok(());
+    }
+
+    let (width, height) = image_dimensions(data, mime_type)
+        .filter(|(width, height)| *width > 0 && *height > 0)
+        .ok_or_else(|| AppError::BadRequest("Unable to read image dimensions".to_string()))?;
+    let ratio = width as f64 / height as f64;
+
+    if min_ratio.is_some_and(|min| ratio < min) || max_ratio.is_some_and(|max| ratio > max) {
+        return Err(AppError::BadRequest(format!(
+            "Image aspect ratio {:.2} is outside the allowed range {} to {}",
+            ratio,
+            min_ratio.map(|min| min.to_string()).unwrap_or_else(|| "any".to_string()),
+            max_ratio.map(|max| max.to_string()).unwrap_or_else(|| "any".to_string()),
+        )));
+    }
+    Ok(())
+}
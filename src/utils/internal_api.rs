@@ -0,0 +1,51 @@
+use chrono::Utc;
+use actix_web::dev::ServiceRequest;
+use actix_web::http::Method;
+use std::env;
+use crate::utils::jwt::{Claims, ROLE_USER, SCOPE_API};
+use crate::utils::password::secrets_match;
+
+pub const INTERNAL_API_KEY_HEADER: &str = "X-Internal-Api-Key";
+
+// Read-only summaries internal dashboards may call, unless INTERNAL_API_PATHS overrides them
+const DEFAULT_INTERNAL_API_PATHS: &[&str] = &[
+    "/v1/activity/weekly",
+    "/v1/activity/category-trend",
+    "/v1/activity/goal-trend",
+];
+
+fn path_allowed(path: &str) -> bool {
+    let path = path.trim_end_matches('/');
+    match env::var("INTERNAL_API_PATHS") {
+        Ok(paths) if !paths.is_empty() => paths.split(',').map(str::trim).any(|allowed| allowed == path),
+        _ => DEFAULT_INTERNAL_API_PATHS.contains(&path),
+    }
+}
+
+/// Claims for a request carrying a valid `X-Internal-Api-Key`, acting as the account in
+/// `INTERNAL_SERVICE_EMAIL`. Only GET/HEAD on the allowed paths qualify; disabled unless both
+/// `INTERNAL_API_KEY` and `INTERNAL_SERVICE_EMAIL` are set. Anything else falls back to JWT auth.
+pub fn internal_service_claims(req: &ServiceRequest) -> Option<Claims> {
+    let secret = env::var("INTERNAL_API_KEY").ok().filter(|secret| !secret.is_empty())?;
+    let service_email = env::var("INTERNAL_SERVICE_EMAIL").ok().filter(|email| !email.is_empty())?;
+
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return None;
+    }
+    if !path_allowed(req.path()) {
+        return None;
+    }
+
+    let given = req.headers().get(INTERNAL_API_KEY_HEADER)?.to_str().ok()?;
+    if !secrets_match(&secret, given) {
+        return None;
+    }
+
+    Some(Claims {
+        sub: service_email,
+        exp: usize::MAX,
+        scope: SCOPE_API.to_string(),
+        role: ROLE_USER.to_string(),
+        iat: Utc::now().timestamp() as usize,
+    })
+}
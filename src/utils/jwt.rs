@@ -1,39 +1,49 @@
-use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey, Algorithm};
+use jsonwebtoken::{decode, Validation, DecodingKey, Algorithm};
 use serde::{Deserialize, Serialize};
 use std::env;
 use actix_web_httpauth::extractors::bearer::BearerAuth;
 use actix_web::dev::ServiceRequest;
-use actix_web::{Error, HttpMessage};
+use actix_web::{web, Error, HttpMessage};
 use chrono::Utc;
+use crate::errors::AppError;
+use crate::utils::cookie::{cookie_auth_enabled, AUTH_COOKIE_NAME};
+use crate::utils::internal_api::internal_service_claims;
+use lazy_static::lazy_static;
+use moka::sync::Cache;
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // Subject (e.g., user email)
     pub exp: usize,  // Expiration time
+    #[serde(default = "default_scope")]
+    pub scope: String, // "session" for login tokens, "api" for long-lived integration tokens
+    #[serde(default = "default_role")]
+    pub role: String, // "user" or "admin" as of issuing; admin checks re-read users.role
+    #[serde(default)]
+    pub iat: usize, // Issued at; 0 on tokens minted before it was recorded
 }
 
-/// Generates a JWT token for the given email
-pub fn generate_token(email: &str) -> Result<String, jsonwebtoken::errors::Error> {
-    let expiration = Utc::now()
-        .checked_add_signed(chrono::Duration::days(7))
-        .expect("Invalid timestamp")
-        .timestamp() as usize;
+pub const SCOPE_SESSION: &str = "session";
+pub const SCOPE_API: &str = "api";
+pub const SCOPE_VERIFY_EMAIL: &str = "verify_email";
+pub const SCOPE_CHANGE_EMAIL: &str = "change_email";
 
-    let claims = Claims {
-        sub: email.to_string(),
-        exp: expiration,
-    };
+pub const ROLE_USER: &str = "user";
+pub const ROLE_ADMIN: &str = "admin";
 
-    let jwt_secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(jwt_secret.as_ref()),
-    )
+// Tokens issued before scopes existed are session tokens
+fn default_scope() -> String {
+    SCOPE_SESSION.to_string()
+}
+
+// Tokens issued before roles existed belong to regular users
+fn default_role() -> String {
+    ROLE_USER.to_string()
 }
 
 /// Async token validation using spawn_blocking for CPU-bound operations
-async fn validate_token_async(token: &str, jwt_secret: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+pub async fn validate_token_async(token: &str, jwt_secret: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
     let token = token.to_owned();
     let secret = jwt_secret.to_owned();
     
@@ -49,22 +59,197 @@ async fn validate_token_async(token: &str, jwt_secret: &str) -> Result<Claims, j
     .unwrap_or_else(|_| Err(jsonwebtoken::errors::Error::from(jsonwebtoken::errors::ErrorKind::InvalidToken)))
 }
 
+/// Claims reported by token introspection. `iss` and `aud` are not set on tokens we issue but
+/// are passed through when present.
+#[derive(Debug, Deserialize)]
+pub struct IntrospectedClaims {
+    pub sub: String,
+    pub exp: usize,
+    #[serde(default = "default_scope")]
+    pub scope: String,
+    pub iss: Option<String>,
+    pub aud: Option<serde_json::Value>,
+    #[serde(default)]
+    pub iat: usize,
+}
+
+/// Decodes a token for introspection with the same signature and expiry checks as request
+/// authentication. Returns `None` for anything that would not authenticate a request.
+pub async fn introspect_token(token: &str, jwt_secret: &str) -> Option<IntrospectedClaims> {
+    let token = token.to_owned();
+    let secret = jwt_secret.to_owned();
+
+    let claims = actix_web::rt::task::spawn_blocking(move || {
+        let mut validation = Validation::new(Algorithm::HS256);
+        // Audiences are reported, not enforced
+        validation.validate_aud = false;
+        decode::<IntrospectedClaims>(&token, &DecodingKey::from_secret(secret.as_ref()), &validation)
+            .map(|data| data.claims)
+    })
+    .await
+    .ok()?
+    .ok()?;
+
+    let now = Utc::now().timestamp() as usize;
+    if claims.exp < now || (claims.scope != SCOPE_SESSION && claims.scope != SCOPE_API) {
+        return None;
+    }
+    Some(claims)
+}
+
+// When enabled, tokens are only accepted while their `sub` still names an account
+pub fn validate_sub_exists() -> bool {
+    env::var("VALIDATE_SUB_EXISTS").map(|v| v == "true").unwrap_or(false)
+}
+
+// When enabled, tokens issued before the account's last email change (or, for a re-registered
+// address, before the account was created) are rejected
+pub fn revoke_tokens_on_email_change() -> bool {
+    env::var("REVOKE_TOKENS_ON_EMAIL_CHANGE").map(|v| v == "true").unwrap_or(false)
+}
+
+// How long an account lookup is reused (SUB_EXISTS_CACHE_TTL_SECONDS, default 30)
+fn sub_exists_cache_ttl() -> Duration {
+    Duration::from_secs(
+        env::var("SUB_EXISTS_CACHE_TTL_SECONDS")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(30),
+    )
+}
+
+// The account currently holding an address, as far as token checks are concerned
+#[derive(Clone, Copy)]
+enum Subject {
+    Missing,
+    // Tokens issued before this Unix time are revoked (account creation or last email change)
+    Active { tokens_valid_after: Option<i64> },
+}
+
+lazy_static! {
+    // The account behind each recently seen `sub`, so a busy client costs one lookup per TTL
+    static ref SUBJECTS: Cache<String, Subject> = Cache::builder()
+        .max_capacity(100_000)
+        .time_to_live(sub_exists_cache_ttl())
+        .build();
+}
+
+/// Drops the cached account lookup for `email`, e.g. once the address moves to or from an account.
+pub fn forget_subject(email: &str) {
+    SUBJECTS.invalidate(email);
+}
+
+async fn lookup_subject(pool: &sqlx::PgPool, email: &str) -> Result<Subject, AppError> {
+    if let Some(subject) = SUBJECTS.get(email) {
+        return Ok(subject);
+    }
+
+    let subject = sqlx::query!(
+        "SELECT tokens_valid_after FROM users WHERE email = $1",
+        email
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| AppError::InternalServerError("Database error".to_string()))?
+    .map_or(Subject::Missing, |user| Subject::Active {
+        tokens_valid_after: user.tokens_valid_after.map(|cutoff| cutoff.timestamp()),
+    });
+
+    SUBJECTS.insert(email.to_string(), subject);
+    Ok(subject)
+}
+
+// Tokens issued up to this many seconds before a cutoff are still accepted. The cutoff and `iat`
+// are both whole seconds from the app clock, and this absorbs skew between instances, so a token
+// issued right after an email change on another instance is not mistaken for an older one.
+const REVOCATION_LEEWAY_SECONDS: i64 = 5;
+
+// Applies the enabled checks to the account currently holding a token's `sub`
+fn judge_subject(subject: Subject, iat: usize, require_exists: bool, check_revocation: bool) -> Result<(), AppError> {
+    match subject {
+        Subject::Active { tokens_valid_after: Some(cutoff) } if check_revocation && (iat as i64) + REVOCATION_LEEWAY_SECONDS < cutoff => {
+            Err(AppError::Unauthorized("Token has been revoked".to_string()))
+        }
+        // An address no account holds any more has moved away, so its tokens are revoked too
+        Subject::Missing if require_exists || check_revocation => {
+            Err(AppError::Unauthorized("User no longer exists".to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks that a token for `sub` issued at `iat` still belongs to the account holding that
+/// address. Tokens name the account by email, so with `REVOKE_TOKENS_ON_EMAIL_CHANGE` ones issued
+/// before an email change, or for a previous holder of a re-registered address, are rejected with
+/// 401. Without either that or `VALIDATE_SUB_EXISTS` no lookup is made.
+pub async fn check_subject(pool: &sqlx::PgPool, sub: &str, iat: usize) -> Result<(), AppError> {
+    verify_subject(pool, sub, iat, validate_sub_exists(), revoke_tokens_on_email_change()).await
+}
+
+/// `check_subject` with the checks passed in rather than read from the environment.
+pub async fn verify_subject(
+    pool: &sqlx::PgPool,
+    sub: &str,
+    iat: usize,
+    require_exists: bool,
+    check_revocation: bool,
+) -> Result<(), AppError> {
+    if !require_exists && !check_revocation {
+        return Ok(());
+    }
+
+    let subject = lookup_subject(pool, sub).await?;
+    judge_subject(subject, iat, require_exists, check_revocation)
+}
+
 /// Async validator for Bearer authentication
+///
+/// Credentials are optional so a missing or malformed `Authorization` header reaches us instead of
+/// actix-web-httpauth's empty challenge response, and every failure shares the JSON error shape.
 pub async fn validator(
     req: ServiceRequest,
-    credentials: BearerAuth,
+    credentials: Option<BearerAuth>,
 ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+    // Trusted internal callers may read allow-listed summaries as the configured service account
+    if let Some(claims) = internal_service_claims(&req) {
+        req.extensions_mut().insert(claims);
+        return Ok(req);
+    }
+
+    // The bearer header wins; the auth cookie is only consulted when cookie auth is enabled
+    let token = match credentials {
+        Some(credentials) => credentials.token().to_string(),
+        None => match req.cookie(AUTH_COOKIE_NAME).filter(|_| cookie_auth_enabled()) {
+            Some(cookie) => cookie.value().to_string(),
+            None => return Err((AppError::Unauthorized("Missing or malformed bearer token".to_string()).into(), req)),
+        },
+    };
+
     let jwt_secret = match env::var("JWT_SECRET") {
         Ok(secret) => secret,
-        Err(_) => return Err((actix_web::error::ErrorInternalServerError("JWT secret not configured"), req)),
+        Err(_) => return Err((AppError::InternalServerError("JWT secret not configured".to_string()).into(), req)),
     };
 
-    match validate_token_async(credentials.token(), &jwt_secret).await {
+    match validate_token_async(&token, &jwt_secret).await {
         Ok(claims) => {
             // Manual expiration check
             let now = Utc::now().timestamp() as usize;
             if claims.exp < now {
-                return Err((actix_web::error::ErrorUnauthorized("Token expired"), req));
+                return Err((AppError::Unauthorized("Token expired".to_string()).into(), req));
+            }
+
+            // Single-purpose tokens (e.g. email verification) cannot authenticate requests
+            if claims.scope != SCOPE_SESSION && claims.scope != SCOPE_API {
+                return Err((AppError::Unauthorized("Invalid token".to_string()).into(), req));
+            }
+
+            // Signature and expiry outlive the account or address the token was issued for
+            let pool = match req.app_data::<web::Data<sqlx::PgPool>>() {
+                Some(pool) => pool.clone(),
+                None => return Err((AppError::InternalServerError("Database pool not configured".to_string()).into(), req)),
+            };
+            if let Err(err) = check_subject(&pool, &claims.sub, claims.iat).await {
+                return Err((err.into(), req));
             }
             
             req.extensions_mut().insert(claims);
@@ -73,10 +258,69 @@ pub async fn validator(
         Err(e) => {
             let error = match e.kind() {
                 jsonwebtoken::errors::ErrorKind::ExpiredSignature => 
-                    actix_web::error::ErrorUnauthorized("Token expired"),
-                _ => actix_web::error::ErrorUnauthorized("Invalid token"),
+                    AppError::Unauthorized("Token expired".to_string()),
+                _ => AppError::Unauthorized("Invalid token".to_string()),
             };
-            Err((error, req))
+            Err((error.into(), req))
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn active_since(cutoff: i64) -> Subject {
+        Subject::Active { tokens_valid_after: Some(cutoff) }
+    }
+
+    #[test]
+    fn accepts_existing_user() {
+        assert!(judge_subject(active_since(100), 100, true, true).is_ok());
+        assert!(judge_subject(Subject::Active { tokens_valid_after: None }, 0, true, true).is_ok());
+    }
+
+    #[test]
+    fn rejects_deleted_user_only_when_enabled() {
+        assert!(matches!(
+            judge_subject(Subject::Missing, 100, true, false),
+            Err(AppError::Unauthorized(message)) if message == "User no longer exists"
+        ));
+        assert!(judge_subject(Subject::Missing, 100, false, true).is_err());
+        assert!(judge_subject(Subject::Missing, 100, false, false).is_ok());
+    }
+
+    #[test]
+    fn rejects_tokens_issued_before_cutoff_only_when_enabled() {
+        assert!(matches!(
+            judge_subject(active_since(100), 94, false, true),
+            Err(AppError::Unauthorized(message)) if message == "Token has been revoked"
+        ));
+        assert!(judge_subject(active_since(100), 94, true, false).is_ok());
+    }
+
+    #[test]
+    fn tolerates_skew_around_the_cutoff() {
+        // Same second as the change, and a few seconds behind on a skewed instance
+        assert!(judge_subject(active_since(100), 100, false, true).is_ok());
+        assert!(judge_subject(active_since(100), 95, false, true).is_ok());
+    }
+
+    #[tokio::test]
+    async fn lookup_reuses_cached_subject() {
+        // Never connects: a cached subject must be answered without touching the database
+        let pool = sqlx::PgPool::connect_lazy("postgres://nobody@127.0.0.1:1/none").unwrap();
+        let email = "cached-subject@example.com";
+
+        SUBJECTS.insert(email.to_string(), Subject::Missing);
+        assert!(matches!(lookup_subject(&pool, email).await, Ok(Subject::Missing)));
+
+        SUBJECTS.insert(email.to_string(), active_since(42));
+        assert!(matches!(
+            lookup_subject(&pool, email).await,
+            Ok(Subject::Active { tokens_valid_after: Some(42) })
+        ));
+
+        forget_subject(email);
+        assert!(SUBJECTS.get(email).is_none());
+    }
+}
@@ -0,0 +1,28 @@
+use lazy_static::lazy_static;
+use prometheus::{exponential_buckets, Histogram, HistogramOpts, HistogramVec, Registry};
+
+lazy_static! {
+    // Accepted upload sizes, 1KiB..128KiB to cover the 100KiB cap
+    pub static ref UPLOAD_FILE_BYTES: HistogramVec = HistogramVec::new(
+        HistogramOpts::new("upload_file_bytes", "Size of accepted file uploads in bytes")
+            .buckets(exponential_buckets(1024.0, 2.0, 8).unwrap()),
+        &["mime"],
+    )
+    .unwrap();
+
+    // Wall time of the put_object call only, not the body read or the existence check
+    pub static ref S3_PUT_DURATION_SECONDS: Histogram = Histogram::with_opts(
+        HistogramOpts::new("s3_put_duration_seconds", "Duration of S3 put_object calls in seconds"),
+    )
+    .unwrap();
+}
+
+/// Adds the application metrics to the registry served at `/metrics`.
+pub fn register(registry: &Registry) {
+    registry
+        .register(Box::new(UPLOAD_FILE_BYTES.clone()))
+        .expect("Failed to register upload_file_bytes");
+    registry
+        .register(Box::new(S3_PUT_DURATION_SECONDS.clone()))
+        .expect("Failed to register s3_put_duration_seconds");
+}
@@ -1,3 +1,19 @@
 pub mod jwt;
 pub mod validation;
-pub mod s3;
\ No newline at end of file
+pub mod s3;
+pub mod password;
+pub mod units;
+pub mod activity_types;
+pub mod cookie;
+pub mod client_ip;
+pub mod timezone;
+pub mod circuit_breaker;
+pub mod compression;
+pub mod header_limits;
+pub mod cache_control;
+pub mod request_limiter;
+pub mod image;
+pub mod internal_api;
+pub mod metrics;
+pub mod file_token;
+pub mod window_counter;
\ No newline at end of file
@@ -0,0 +1,185 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use lazy_static::lazy_static;
+use log::{info, warn};
+use crate::errors::AppError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Mixes the optional server-side `PASSWORD_PEPPER` into a password before bcrypt sees it.
+///
+/// Without a pepper the password is returned unchanged, so hashes created before the pepper
+/// was configured keep verifying. Changing the pepper needs the old one in `PASSWORD_PEPPER_FALLBACKS`.
+pub fn apply_pepper(password: &str) -> String {
+    match env::var("PASSWORD_PEPPER") {
+        Ok(pepper) if !pepper.is_empty() => hmac_with_pepper(password, &pepper),
+        _ => password.to_string(),
+    }
+}
+
+/// Compares shared secrets without returning early on the first differing byte
+pub fn secrets_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len()
+        && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Outcome of checking a password against the current and previous peppers
+#[derive(Debug, PartialEq)]
+pub enum PepperMatch {
+    Current,
+    Fallback,
+    NoMatch,
+}
+
+// Entry in `PASSWORD_PEPPER_FALLBACKS` standing for "no pepper", for hashes made before one was set
+const NO_PEPPER_FALLBACK: &str = "none";
+
+/// Verifies against the current pepper first, then each previous pepper listed in
+/// `PASSWORD_PEPPER_FALLBACKS` (comma-separated), so a pepper rotation does not lock users out.
+/// A `none` entry tries the bare password, for hashes made before any pepper was configured.
+/// A `Fallback` match means the caller should re-hash with the current pepper.
+pub fn verify_with_pepper_fallbacks(password: &str, hash: &str) -> Result<PepperMatch, bcrypt::BcryptError> {
+    let fallbacks = env::var("PASSWORD_PEPPER_FALLBACKS").unwrap_or_default();
+    verify_with_peppers(password, hash, &apply_pepper(password), &fallbacks)
+}
+
+fn verify_with_peppers(password: &str, hash: &str, current: &str, fallbacks: &str) -> Result<PepperMatch, bcrypt::BcryptError> {
+    if bcrypt::verify(current, hash)? {
+        return Ok(PepperMatch::Current);
+    }
+
+    for pepper in fallbacks.split(',').map(str::trim).filter(|pepper| !pepper.is_empty()) {
+        let candidate = match pepper {
+            NO_PEPPER_FALLBACK => password.to_string(),
+            pepper => hmac_with_pepper(password, pepper),
+        };
+        if bcrypt::verify(candidate, hash)? {
+            return Ok(PepperMatch::Fallback);
+        }
+    }
+
+    Ok(PepperMatch::NoMatch)
+}
+
+// HMAC-SHA256 hex digest, always 64 chars so it stays under bcrypt's 72 byte input limit
+fn hmac_with_pepper(password: &str, pepper: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(pepper.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(password.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+lazy_static! {
+    // Loaded once at startup, lookups are O(1)
+    static ref COMMON_PASSWORDS: HashSet<String> = load_common_passwords();
+}
+
+// Reads `COMMON_PASSWORDS_FILE` when set, otherwise the bundled list
+fn load_common_passwords() -> HashSet<String> {
+    let list = match env::var("COMMON_PASSWORDS_FILE") {
+        Ok(path) => fs::read_to_string(&path).unwrap_or_else(|e| {
+            warn!("Failed to read COMMON_PASSWORDS_FILE {}: {}, using bundled list", path, e);
+            include_str!("common_passwords.txt").to_string()
+        }),
+        Err(_) => include_str!("common_passwords.txt").to_string(),
+    };
+
+    list.lines()
+        .map(|line| line.trim().to_lowercase())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Forces the denylist to load so a bad file is reported at startup rather than on first use.
+pub fn init_common_passwords() {
+    info!("Loaded {} common passwords", COMMON_PASSWORDS.len());
+}
+
+/// Rejects passwords found in the common-password denylist (case-insensitive).
+pub fn validate_not_common_password(password: &str) -> Result<(), AppError> {
+    if COMMON_PASSWORDS.contains(&password.to_lowercase()) {
+        return Err(AppError::BadRequest("Password is too common".to_string()));
+    }
+    Ok(())
+}
+
+// Local parts shorter than this are too likely to appear in unrelated passwords by chance
+const MIN_EMAIL_LOCAL_PART_MATCH: usize = 4;
+
+/// Rejects passwords that equal the email or contain its local part (case-insensitive).
+pub fn validate_password_not_email(password: &str, email: &str) -> Result<(), AppError> {
+    let password = password.to_lowercase();
+    let email = email.to_lowercase();
+    let local_part = email.split('@').next().unwrap_or_default();
+
+    if password == email
+        || (local_part.chars().count() >= MIN_EMAIL_LOCAL_PART_MATCH && password.contains(local_part))
+    {
+        return Err(AppError::BadRequest("Password must not contain your email".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lowest cost bcrypt accepts, to keep the tests fast
+    const COST: u32 = 4;
+
+    #[test]
+    fn current_pepper_matches_first() {
+        let hash = bcrypt::hash(hmac_with_pepper("Str0ngPassw", "new"), COST).unwrap();
+        let current = hmac_with_pepper("Str0ngPassw", "new");
+        assert_eq!(verify_with_peppers("Str0ngPassw", &hash, &current, "old").unwrap(), PepperMatch::Current);
+    }
+
+    #[test]
+    fn previous_pepper_matches_as_fallback() {
+        let hash = bcrypt::hash(hmac_with_pepper("Str0ngPassw", "old"), COST).unwrap();
+        let current = hmac_with_pepper("Str0ngPassw", "new");
+        assert_eq!(verify_with_peppers("Str0ngPassw", &hash, &current, "older, old").unwrap(), PepperMatch::Fallback);
+        assert_eq!(verify_with_peppers("Str0ngPassw", &hash, &current, "").unwrap(), PepperMatch::NoMatch);
+    }
+
+    #[test]
+    fn unpeppered_hash_needs_an_explicit_none_fallback() {
+        let hash = bcrypt::hash("Str0ngPassw", COST).unwrap();
+        let current = hmac_with_pepper("Str0ngPassw", "new");
+        assert_eq!(verify_with_peppers("Str0ngPassw", &hash, &current, "old,").unwrap(), PepperMatch::NoMatch);
+        assert_eq!(verify_with_peppers("Str0ngPassw", &hash, &current, "old,none").unwrap(), PepperMatch::Fallback);
+        assert_eq!(verify_with_peppers("wrong", &hash, &hmac_with_pepper("wrong", "new"), "none").unwrap(), PepperMatch::NoMatch);
+    }
+
+    #[test]
+    fn common_passwords_are_rejected_case_insensitively() {
+        assert!(validate_not_common_password("password123").is_err());
+        assert!(validate_not_common_password("QWERTYUIOP").is_err());
+        assert!(validate_not_common_password("P@ssW0rd").is_err());
+    }
+
+    #[test]
+    fn uncommon_passwords_are_accepted() {
+        assert!(validate_not_common_password("Str0ngPassw").is_ok());
+        assert!(validate_not_common_password("password1234567").is_ok());
+    }
+
+    #[test]
+    fn passwords_containing_the_email_are_rejected() {
+        assert!(validate_password_not_email("Jane.Doe@Example.com", "jane.doe@example.com").is_err());
+        assert!(validate_password_not_email("xxJANE.DOE99", "jane.doe@example.com").is_err());
+    }
+
+    #[test]
+    fn short_local_parts_only_block_the_full_email() {
+        assert!(validate_password_not_email("bob-the-builder", "bob@example.com").is_ok());
+        assert!(validate_password_not_email("bob@example.com", "bob@example.com").is_err());
+        assert!(validate_password_not_email("Str0ngPassw", "jane.doe@example.com").is_ok());
+    }
+}
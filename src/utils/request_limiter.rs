@@ -0,0 +1,40 @@
+use std::env;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use crate::errors::AppError;
+
+// Probes must keep answering while the server is saturated
+const EXEMPT_PATHS: &[&str] = &["/ready", "/metrics"];
+
+/// Global cap on in-flight requests, shared by every worker
+#[derive(Clone)]
+pub struct RequestLimiter {
+    semaphore: Option<Arc<Semaphore>>,
+}
+
+impl RequestLimiter {
+    /// Reads `MAX_CONCURRENT_REQUESTS`; unlimited when unset or zero
+    pub fn from_env() -> Self {
+        let semaphore = env::var("MAX_CONCURRENT_REQUESTS")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .filter(|permits| *permits > 0)
+            .map(|permits| Arc::new(Semaphore::new(permits)));
+
+        RequestLimiter { semaphore }
+    }
+
+    /// Takes a permit for the request, or fails fast when every permit is in use.
+    /// The permit is released when dropped.
+    pub fn try_admit(&self, path: &str) -> Result<Option<OwnedSemaphorePermit>, AppError> {
+        let semaphore = match &self.semaphore {
+            Some(semaphore) if !EXEMPT_PATHS.contains(&path) => semaphore,
+            _ => return Ok(None),
+        };
+
+        semaphore.clone()
+            .try_acquire_owned()
+            .map(Some)
+            .map_err(|_| AppError::TooManyRequests("Server is busy, try again later".to_string()))
+    }
+}
@@ -1,12 +1,88 @@
 use aws_sdk_s3::Client as S3Client;
+use aws_sdk_s3::types::ServerSideEncryption;
 use aws_config::ConfigLoader;
 use aws_types::region::Region;
+use lazy_static::lazy_static;
+use std::time::Duration;
+use crate::utils::circuit_breaker::CircuitBreaker;
+
+lazy_static! {
+    // Shared by every S3 call so a degraded S3 fast-fails instead of tying up workers
+    pub static ref S3_BREAKER: CircuitBreaker = CircuitBreaker::new(
+        std::env::var("S3_BREAKER_FAILURE_THRESHOLD").ok().and_then(|v| v.parse().ok()).unwrap_or(5),
+        Duration::from_secs(std::env::var("S3_BREAKER_COOLDOWN_SECONDS").ok().and_then(|v| v.parse().ok()).unwrap_or(30)),
+    );
+}
 
 pub async fn create_s3_client() -> S3Client {
     let aws_config = ConfigLoader::default()
-        .region(std::env::var("AWS_REGION").ok().map(Region::new))
+        .region(region().map(Region::new))
         .load()
         .await;
 
     S3Client::new(&aws_config)
-}
\ No newline at end of file
+}
+
+/// Optional deployment profile (`S3_PROFILE`, e.g. `eu` or `ap-southeast`), normalized to the
+/// upper-case suffix used by the per-profile variables
+pub fn s3_profile() -> Option<String> {
+    std::env::var("S3_PROFILE")
+        .ok()
+        .filter(|profile| !profile.is_empty())
+        .map(|profile| profile.to_uppercase().replace('-', "_"))
+}
+
+// `<name>_<PROFILE>` when a profile is selected and that variable is set, otherwise `<name>`
+fn profile_var(name: &str) -> Option<String> {
+    s3_profile()
+        .and_then(|profile| std::env::var(format!("{}_{}", name, profile)).ok())
+        .filter(|value| !value.is_empty())
+        .or_else(|| std::env::var(name).ok().filter(|value| !value.is_empty()))
+}
+
+/// Upload bucket: `AWS_S3_BUCKET_<PROFILE>`, falling back to `AWS_S3_BUCKET`
+pub fn bucket_name() -> Option<String> {
+    profile_var("AWS_S3_BUCKET")
+}
+
+/// S3 client region: `AWS_REGION_<PROFILE>`, falling back to `AWS_REGION`
+pub fn region() -> Option<String> {
+    profile_var("AWS_REGION")
+}
+
+/// Server-side encryption settings for uploaded objects
+pub struct SseConfig {
+    pub algorithm: ServerSideEncryption,
+    pub kms_key_id: Option<String>,
+}
+
+/// Reads `S3_SSE` (`AES256` or `aws:kms`) and the optional `S3_SSE_KMS_KEY_ID`.
+/// Returns `Ok(None)` when unset so uploads keep the bucket's default behavior.
+pub fn sse_config() -> Result<Option<SseConfig>, String> {
+    let algorithm = match std::env::var("S3_SSE") {
+        Ok(value) if !value.is_empty() => value,
+        _ => return Ok(None),
+    };
+
+    let algorithm = match algorithm.as_str() {
+        "AES256" => ServerSideEncryption::Aes256,
+        "aws:kms" => ServerSideEncryption::AwsKms,
+        other => return Err(format!("Unsupported S3_SSE value: {}", other)),
+    };
+
+    let kms_key_id = std::env::var("S3_SSE_KMS_KEY_ID").ok().filter(|id| !id.is_empty());
+    if kms_key_id.is_some() && algorithm != ServerSideEncryption::AwsKms {
+        return Err("S3_SSE_KMS_KEY_ID requires S3_SSE=aws:kms".to_string());
+    }
+
+    Ok(Some(SseConfig { algorithm, kms_key_id }))
+}
+
+/// Splits an `s3://bucket/key` URI into its bucket and key
+pub fn parse_s3_uri(uri: &str) -> Option<(&str, &str)> {
+    let (bucket, key) = uri.strip_prefix("s3://")?.split_once('/')?;
+    if bucket.is_empty() || key.is_empty() {
+        return None;
+    }
+    Some((bucket, key))
+}
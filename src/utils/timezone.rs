@@ -0,0 +1,156 @@
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use crate::errors::AppError;
+
+/// A validated timezone, either a fixed UTC offset or an IANA zone
+#[derive(Clone, Copy, Debug)]
+pub enum Timezone {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl Timezone {
+    /// Canonical form stored on the profile, e.g. `+07:00` or `Asia/Jakarta`
+    pub fn canonical(&self) -> String {
+        match self {
+            Timezone::Fixed(offset) => offset.to_string(),
+            Timezone::Named(tz) => tz.name().to_string(),
+        }
+    }
+
+    /// Form accepted by Postgres `AT TIME ZONE`. Bare offsets are read there as POSIX specs with
+    /// the sign inverted, so fixed offsets are spelled out as `<+07:00>-07:00`.
+    pub fn pg_name(&self) -> String {
+        match self {
+            Timezone::Fixed(offset) => {
+                let inverted = FixedOffset::east_opt(-offset.local_minus_utc()).unwrap_or(*offset);
+                format!("<{}>{}", offset, inverted)
+            }
+            Timezone::Named(tz) => tz.name().to_string(),
+        }
+    }
+
+    /// Current local calendar date in this timezone
+    pub fn today(&self) -> NaiveDate {
+        self.local_date(Utc::now())
+    }
+
+    /// Local calendar date of the instant `at` in this timezone
+    pub fn local_date(&self, at: DateTime<Utc>) -> NaiveDate {
+        match self {
+            Timezone::Fixed(offset) => at.with_timezone(offset).date_naive(),
+            Timezone::Named(tz) => at.with_timezone(tz).date_naive(),
+        }
+    }
+
+    /// UTC range `[start, end)` covering the local calendar day `date` in this timezone
+    pub fn day_bounds_utc(&self, date: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        let next = date.succ_opt().unwrap_or(date);
+        (self.local_midnight_utc(date), self.local_midnight_utc(next))
+    }
+
+    // Midnight can fall in a DST gap for some zones, in which case the day starts an hour later
+    fn local_midnight_utc(&self, date: NaiveDate) -> DateTime<Utc> {
+        let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is a valid time");
+        let local = match self {
+            Timezone::Fixed(offset) => offset.from_local_datetime(&midnight).earliest().map(|at| at.with_timezone(&Utc)),
+            Timezone::Named(tz) => tz.from_local_datetime(&midnight)
+                .earliest()
+                .or_else(|| tz.from_local_datetime(&(midnight + Duration::hours(1))).earliest())
+                .map(|at| at.with_timezone(&Utc)),
+        };
+        local.unwrap_or_else(|| Utc.from_utc_datetime(&midnight))
+    }
+}
+
+/// Parses a fixed offset (`+07:00`, `-0330`), `Z` for UTC, or an IANA name (`Asia/Jakarta`)
+pub fn parse_timezone(value: &str) -> Result<Timezone, AppError> {
+    let value = value.trim();
+
+    if value.eq_ignore_ascii_case("Z") {
+        return Ok(Timezone::Named(Tz::UTC));
+    }
+
+    if value.starts_with('+') || value.starts_with('-') {
+        return parse_offset(value)
+            .map(Timezone::Fixed)
+            .ok_or_else(|| AppError::BadRequest(format!("Invalid timezone offset: {}", value)));
+    }
+
+    value.parse::<Tz>()
+        .map(Timezone::Named)
+        .map_err(|_| AppError::BadRequest(format!("Invalid timezone: {}", value)))
+}
+
+/// Picks the explicit `tz` query parameter when given, otherwise the user's saved timezone,
+/// otherwise UTC. An empty `tz=` counts as not given.
+pub fn resolve_timezone(requested: Option<&str>, saved: Option<&str>) -> Result<Timezone, AppError> {
+    let requested = requested.filter(|value| !value.trim().is_empty());
+    match requested.or(saved) {
+        Some(value) => parse_timezone(value),
+        None => Ok(Timezone::Named(Tz::UTC)),
+    }
+}
+
+fn parse_offset(value: &str) -> Option<FixedOffset> {
+    let sign = if value.starts_with('-') { -1 } else { 1 };
+    let digits: String = value[1..].chars().filter(|c| *c != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    if hours > 14 || minutes > 59 {
+        return None;
+    }
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonical(value: &str) -> String {
+        parse_timezone(value).unwrap().canonical()
+    }
+
+    #[test]
+    fn parses_offsets_utc_and_iana_names() {
+        assert_eq!(canonical("+07:00"), "+07:00");
+        assert_eq!(canonical("-0330"), "-03:30");
+        assert_eq!(canonical(" +14:00 "), "+14:00");
+        assert_eq!(canonical("Z"), "UTC");
+        assert_eq!(canonical("z"), "UTC");
+        assert_eq!(canonical("Asia/Jakarta"), "Asia/Jakarta");
+    }
+
+    #[test]
+    fn rejects_malformed_timezones() {
+        for value in ["+7", "+07:0", "+15:00", "+07:60", "+ab:cd", "Mars/Olympus", ""] {
+            assert!(parse_timezone(value).is_err(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn explicit_timezone_wins_over_saved_one() {
+        assert_eq!(resolve_timezone(Some("Asia/Tokyo"), Some("+07:00")).unwrap().canonical(), "Asia/Tokyo");
+        assert_eq!(resolve_timezone(Some(" "), Some("+07:00")).unwrap().canonical(), "+07:00");
+        assert_eq!(resolve_timezone(None, None).unwrap().canonical(), "UTC");
+    }
+
+    #[test]
+    fn fixed_offsets_are_inverted_for_postgres() {
+        assert_eq!(parse_timezone("+07:00").unwrap().pg_name(), "<+07:00>-07:00");
+        assert_eq!(parse_timezone("Asia/Jakarta").unwrap().pg_name(), "Asia/Jakarta");
+    }
+
+    #[test]
+    fn day_bounds_follow_the_local_calendar_day() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let (start, end) = parse_timezone("+07:00").unwrap().day_bounds_utc(date);
+        assert_eq!(start.to_rfc3339(), "2024-02-29T17:00:00+00:00");
+        assert_eq!(end.to_rfc3339(), "2024-03-01T17:00:00+00:00");
+    }
+}
@@ -0,0 +1,80 @@
+use serde::Serializer;
+use std::env;
+
+const LBS_PER_KG: f64 = 2.204_622_621_8;
+const CM_PER_INCH: f64 = 2.54;
+
+/// Converts a weight between KG and LBS. Units are expected to be validated already.
+pub fn convert_weight(value: f64, from: &str, to: &str) -> f64 {
+    match (from, to) {
+        ("KG", "LBS") => value * LBS_PER_KG,
+        ("LBS", "KG") => value / LBS_PER_KG,
+        _ => value,
+    }
+}
+
+/// Converts a height between CM and INCH. Units are expected to be validated already.
+pub fn convert_height(value: f64, from: &str, to: &str) -> f64 {
+    match (from, to) {
+        ("CM", "INCH") => value / CM_PER_INCH,
+        ("INCH", "CM") => value * CM_PER_INCH,
+        _ => value,
+    }
+}
+
+
+// Upper bound keeps 10^decimals well inside f64's exact integer range
+const MAX_MEASUREMENT_DECIMALS: i32 = 6;
+
+/// Decimals kept for weight/height in responses (`MEASUREMENT_DECIMALS`, default 2)
+pub fn measurement_decimals() -> i32 {
+    env::var("MEASUREMENT_DECIMALS")
+        .ok()
+        .and_then(|value| value.parse::<i32>().ok())
+        .map(|value| value.clamp(0, MAX_MEASUREMENT_DECIMALS))
+        .unwrap_or(2)
+}
+
+/// Rounds to `measurement_decimals()` places, so converted values like 70.30000000000001 come out as 70.3
+pub fn round_measurement(value: f64) -> f64 {
+    let factor = 10f64.powi(measurement_decimals());
+    (value * factor).round() / factor
+}
+
+/// `serialize_with` helper for optional weight/height fields
+pub fn serialize_measurement<S: Serializer>(value: &Option<f64>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => serializer.serialize_some(&round_measurement(*value)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn converts_weight_both_ways() {
+        assert_close(convert_weight(100.0, "KG", "LBS"), 220.46226218);
+        assert_close(convert_weight(220.46226218, "LBS", "KG"), 100.0);
+        assert_close(convert_weight(70.0, "KG", "KG"), 70.0);
+    }
+
+    #[test]
+    fn converts_height_both_ways() {
+        assert_close(convert_height(254.0, "CM", "INCH"), 100.0);
+        assert_close(convert_height(70.0, "INCH", "CM"), 177.8);
+        assert_close(convert_height(180.0, "CM", "CM"), 180.0);
+    }
+
+    #[test]
+    fn round_trips_survive_response_rounding() {
+        let pounds = convert_weight(70.3, "KG", "LBS");
+        assert_eq!(round_measurement(convert_weight(pounds, "LBS", "KG")), 70.3);
+        assert_eq!(round_measurement(pounds), 154.98);
+    }
+}
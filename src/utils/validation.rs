@@ -1,11 +1,8 @@
 use regex::Regex;
-use validator::Validate;
+use serde::{de::Error as _, Deserialize, Deserializer};
+use lazy_static::lazy_static;
 use crate::errors::AppError;
-
-pub fn validate_payload<T: Validate>(payload: &T) -> Result<(), AppError> {
-    payload.validate()
-        .map_err(|err| AppError::BadRequest(err.to_string()))
-}
+use std::env;
 
 pub fn validate_preference(preference: &str) -> Result<(), AppError> {
     if !["CARDIO", "WEIGHT"].contains(&preference) {
@@ -28,12 +25,142 @@ pub fn validate_height_unit(height_unit: &str) -> Result<(), AppError> {
     Ok(())
 }
 
-// Regex validation for uri
-pub fn validate_url(uri: &str) -> Result<(), AppError> {
-    let re = Regex::new(r"^https?://[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}(/[^\s]*)?$").unwrap();
-    
-    if !re.is_match(uri) {
-        return Err(AppError::BadRequest("Invalid URI. It should be URI".to_string()));
+// serde helper for minute counts: integral numbers (including `30.0`) are accepted, fractional
+// or non-numeric values fail with a message naming the problem instead of serde's type error
+pub fn deserialize_whole_minutes<'de, D>(deserializer: D) -> Result<Option<i32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = match Option::<serde_json::Value>::deserialize(deserializer)? {
+        None | Some(serde_json::Value::Null) => return Ok(None),
+        Some(value) => value,
+    };
+    let minutes = value.as_f64()
+        .ok_or_else(|| D::Error::custom("Duration must be a number of minutes"))?;
+    if minutes.fract() != 0.0 {
+        return Err(D::Error::custom("Duration must be a whole number of minutes"));
+    }
+    if minutes < i32::MIN as f64 || minutes > i32::MAX as f64 {
+        return Err(D::Error::custom("Duration is out of range"));
+    }
+    Ok(Some(minutes as i32))
+}
+
+pub const DEFAULT_PAGE_LIMIT: i64 = 5;
+pub const MAX_PAGE_LIMIT: i64 = 100;
+
+// Normalizes list pagination, falling back to the defaults for missing or out-of-range values
+pub fn clamp_pagination(limit: Option<i64>, offset: Option<i64>) -> (i64, i64) {
+    let limit = match limit {
+        Some(limit) if limit > 0 => limit.min(MAX_PAGE_LIMIT),
+        _ => DEFAULT_PAGE_LIMIT,
+    };
+    let offset = offset.filter(|offset| *offset >= 0).unwrap_or(0);
+    (limit, offset)
+}
+
+/// Largest number of items one batch request (bulk create, CSV import) may carry (`MAX_BATCH_SIZE`).
+pub fn max_batch_size() -> usize {
+    env::var("MAX_BATCH_SIZE")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(100)
+}
+
+// Shared guard for batch endpoints, checked before any item is processed
+pub fn validate_batch_size(count: usize) -> Result<(), AppError> {
+    let limit = max_batch_size();
+    if count > limit {
+        return Err(AppError::BadRequest(format!("Batch size exceeds limit of {}", limit)));
+    }
+    Ok(())
+}
+
+lazy_static! {
+    static ref URL_REGEX: Regex = Regex::new(r"^https?://[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}(/[^\s]*)?$").unwrap();
+}
+
+// Single source of truth for URL fields; the error names the offending field
+pub fn validate_url(field: &str, uri: &str) -> Result<(), AppError> {
+    if uri.is_empty() {
+        return Err(AppError::BadRequest(format!("{} cannot be empty if provided", field)));
+    }
+    if !URL_REGEX.is_match(uri) {
+        return Err(AppError::BadRequest(format!("{} must be an http(s) URL with a valid host", field)));
     }
     Ok(())
 }
+
+// Strips path separators, null bytes and control characters from a client-supplied file name,
+// also dropping any leading dots so the result can never be `..` or a hidden file
+pub fn sanitize_filename(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .filter(|c| *c != '/' && *c != '\\' && !c.is_control())
+        .collect();
+    cleaned.trim_start_matches('.').trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_filename_strips_traversal() {
+        assert_eq!(sanitize_filename("../../etc/passwd"), "etcpasswd");
+        assert_eq!(sanitize_filename("..\\..\\boot.ini"), "boot.ini");
+        assert_eq!(sanitize_filename(".."), "");
+    }
+
+    #[test]
+    fn sanitize_filename_strips_nulls_and_control_chars() {
+        assert_eq!(sanitize_filename("avatar\0.png"), "avatar.png");
+        assert_eq!(sanitize_filename("line\r\nbreak\t.jpg"), "linebreak.jpg");
+    }
+
+    #[test]
+    fn sanitize_filename_keeps_normal_names() {
+        assert_eq!(sanitize_filename("profile photo (1).png"), "profile photo (1).png");
+        assert_eq!(sanitize_filename("résumé.jpeg"), "résumé.jpeg");
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Minutes {
+        #[serde(default, deserialize_with = "deserialize_whole_minutes")]
+        minutes: Option<i32>,
+    }
+
+    fn minutes(json: &str) -> Result<Option<i32>, String> {
+        serde_json::from_str::<Minutes>(json).map(|parsed| parsed.minutes).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn whole_minutes_accept_integral_numbers() {
+        assert_eq!(minutes(r#"{"minutes": 30}"#), Ok(Some(30)));
+        assert_eq!(minutes(r#"{"minutes": 30.0}"#), Ok(Some(30)));
+        assert_eq!(minutes(r#"{"minutes": null}"#), Ok(None));
+        assert_eq!(minutes("{}"), Ok(None));
+    }
+
+    #[test]
+    fn whole_minutes_reject_fractions_and_non_numbers() {
+        assert!(minutes(r#"{"minutes": 30.5}"#).unwrap_err().starts_with("Duration must be a whole number of minutes"));
+        assert!(minutes(r#"{"minutes": "30"}"#).unwrap_err().starts_with("Duration must be a number of minutes"));
+        assert!(minutes(r#"{"minutes": 3000000000}"#).unwrap_err().starts_with("Duration is out of range"));
+    }
+
+    #[test]
+    fn pagination_defaults_missing_or_invalid_values() {
+        assert_eq!(clamp_pagination(None, None), (DEFAULT_PAGE_LIMIT, 0));
+        assert_eq!(clamp_pagination(Some(0), Some(-1)), (DEFAULT_PAGE_LIMIT, 0));
+        assert_eq!(clamp_pagination(Some(-5), Some(20)), (DEFAULT_PAGE_LIMIT, 20));
+    }
+
+    #[test]
+    fn pagination_caps_the_limit() {
+        assert_eq!(clamp_pagination(Some(10), Some(0)), (10, 0));
+        assert_eq!(clamp_pagination(Some(MAX_PAGE_LIMIT), None), (MAX_PAGE_LIMIT, 0));
+        assert_eq!(clamp_pagination(Some(i64::MAX), Some(i64::MAX)), (MAX_PAGE_LIMIT, i64::MAX));
+    }
+}
@@ -0,0 +1,173 @@
+use moka::sync::Cache;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+struct Window {
+    started: Instant,
+    used: AtomicU32,
+}
+
+/// Fixed-window quota per key, the window starting at the key's first reservation.
+///
+/// Slots are taken with one atomic add before the guarded work runs, so concurrent callers can
+/// never overshoot the limit between checking and counting. A reservation gives its slots back
+/// when dropped unless `commit` keeps them.
+pub struct WindowCounter<K> {
+    window: Duration,
+    windows: Cache<K, Arc<Window>>,
+}
+
+/// Quota state after a reservation, for rate-limit headers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    pub limit: u32,
+    pub remaining: u32,
+    // Seconds until the window resets
+    pub reset_after: u64,
+}
+
+impl Quota {
+    /// `X-RateLimit-*` headers describing this quota
+    pub fn headers(&self) -> [(&'static str, String); 3] {
+        [
+            ("X-RateLimit-Limit", self.limit.to_string()),
+            ("X-RateLimit-Remaining", self.remaining.to_string()),
+            ("X-RateLimit-Reset", self.reset_after.to_string()),
+        ]
+    }
+}
+
+impl<K: Hash + Eq + Send + Sync + 'static> WindowCounter<K> {
+    pub fn new(window: Duration) -> Self {
+        WindowCounter {
+            window,
+            windows: Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(window)
+                .build(),
+        }
+    }
+
+    /// Takes `count` slots of `limit` for `key`. On rejection returns the quota as it stands,
+    /// whose `reset_after` doubles as Retry-After.
+    pub fn reserve(&self, key: K, count: u32, limit: u32) -> Result<Reservation, Quota> {
+        let window = self.windows.get_with(key, || Arc::new(Window {
+            started: Instant::now(),
+            used: AtomicU32::new(0),
+        }));
+
+        let previous = window.used.fetch_add(count, Ordering::AcqRel);
+        let reset_after = self.window.saturating_sub(window.started.elapsed()).as_secs().max(1);
+        if previous.saturating_add(count) > limit {
+            window.used.fetch_sub(count, Ordering::AcqRel);
+            return Err(Quota { limit, remaining: limit.saturating_sub(previous), reset_after });
+        }
+
+        Ok(Reservation {
+            window,
+            count,
+            quota: Quota {
+                limit,
+                remaining: limit - previous - count,
+                reset_after,
+            },
+        })
+    }
+}
+
+/// Slots held for one caller, released on drop unless committed
+pub struct Reservation {
+    window: Arc<Window>,
+    count: u32,
+    quota: Quota,
+}
+
+impl Reservation {
+    /// Keeps `used` of the reserved slots and gives the rest back
+    pub fn commit(mut self, used: u32) -> Quota {
+        let used = used.min(self.count);
+        self.quota.remaining += self.count - used;
+        self.count -= used;
+        self.quota
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if self.count > 0 {
+            self.window.used.fetch_sub(self.count, Ordering::AcqRel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_up_to_the_limit_then_rejects() {
+        let counter = WindowCounter::new(Duration::from_secs(60));
+        for expected_remaining in [2, 1, 0] {
+            let reservation = counter.reserve("user", 1, 3).expect("within the limit");
+            assert_eq!(reservation.commit(1).remaining, expected_remaining);
+        }
+
+        let rejected = counter.reserve("user", 1, 3).err().expect("over the limit");
+        assert_eq!(rejected.remaining, 0);
+        assert!((1..=60).contains(&rejected.reset_after));
+        assert!(counter.reserve("other", 1, 3).is_ok());
+    }
+
+    #[test]
+    fn batch_over_the_limit_is_rejected_whole() {
+        let counter = WindowCounter::new(Duration::from_secs(60));
+        counter.reserve("user", 2, 3).unwrap().commit(2);
+
+        assert!(counter.reserve("user", 2, 3).is_err());
+        assert_eq!(counter.reserve("user", 1, 3).unwrap().commit(1).remaining, 0);
+    }
+
+    #[test]
+    fn dropped_reservation_releases_its_slots() {
+        let counter = WindowCounter::new(Duration::from_secs(60));
+        let held = counter.reserve("user", 2, 2).unwrap();
+        assert!(counter.reserve("user", 1, 2).is_err());
+
+        drop(held);
+        assert!(counter.reserve("user", 2, 2).is_ok());
+    }
+
+    #[test]
+    fn partial_commit_returns_unused_slots() {
+        let counter = WindowCounter::new(Duration::from_secs(60));
+        let quota = counter.reserve("user", 3, 5).unwrap().commit(1);
+        assert_eq!(quota.remaining, 4);
+        assert_eq!(counter.reserve("user", 4, 5).unwrap().commit(4).remaining, 0);
+    }
+
+    #[test]
+    fn concurrent_reservations_never_exceed_the_limit() {
+        let counter = Arc::new(WindowCounter::new(Duration::from_secs(60)));
+        let handles: Vec<_> = (0..16)
+            .map(|_| {
+                let counter = counter.clone();
+                std::thread::spawn(move || counter.reserve("user", 1, 5).map(|reservation| reservation.commit(1)).is_ok())
+            })
+            .collect();
+
+        let admitted = handles.into_iter().filter_map(|handle| handle.join().unwrap().then_some(())).count();
+        assert_eq!(admitted, 5);
+    }
+
+    #[test]
+    fn window_resets_after_it_expires() {
+        let counter = WindowCounter::new(Duration::from_millis(50));
+        counter.reserve("user", 1, 1).unwrap().commit(1);
+        assert!(counter.reserve("user", 1, 1).is_err());
+
+        std::thread::sleep(Duration::from_millis(80));
+        assert!(counter.reserve("user", 1, 1).is_ok());
+    }
+}